@@ -0,0 +1,260 @@
+use colored::*;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use time::macros::format_description;
+use time::OffsetDateTime;
+use warp::http::{header, HeaderMap, Method, Response};
+use warp::hyper::body::HttpBody;
+use warp::hyper::Body;
+use warp::path::FullPath;
+use warp::Filter;
+
+use crate::{auth, pages};
+
+/// Access log line format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Common Log Format, followed by the request duration
+    #[default]
+    #[value(alias = "clf")]
+    Common,
+    /// One JSON object per line
+    Json,
+    /// Don't log requests
+    Off,
+}
+
+impl LogFormat {
+    pub fn as_arg(&self) -> &'static str {
+        match self {
+            LogFormat::Common => "common",
+            LogFormat::Json => "json",
+            LogFormat::Off => "off",
+        }
+    }
+}
+
+/// Current time as RFC 3339 (UTC), as used in logs and reports
+pub fn timestamp() -> String {
+    rfc3339(OffsetDateTime::now_utc())
+}
+
+pub fn rfc3339(time: OffsetDateTime) -> String {
+    time.format(&time::format_description::well_known::Rfc3339)
+        .unwrap_or_default()
+}
+
+/// What we know about a request before it is handled
+#[derive(Debug)]
+pub struct RequestInfo {
+    start: Instant,
+    method: Method,
+    path: String,
+    remote: Option<SocketAddr>,
+    headers: HeaderMap,
+}
+
+/// Filter capturing the request details needed for the access log
+pub fn request_info() -> impl Filter<Extract = (RequestInfo,), Error = Infallible> + Clone {
+    warp::any()
+        .map(Instant::now)
+        .and(warp::method())
+        .and(warp::path::full())
+        .and(pages::query())
+        .and(warp::addr::remote())
+        .and(warp::header::headers_cloned())
+        .map(
+            |start, method, path: FullPath, query: String, remote, headers| {
+                let query = without_token(&query);
+                RequestInfo {
+                    start,
+                    method,
+                    path: if query.is_empty() {
+                        path.as_str().to_string()
+                    } else {
+                        format!("{}?{}", path.as_str(), query)
+                    },
+                    remote,
+                    headers,
+                }
+            },
+        )
+}
+
+/// A query string without the access token, which must not end up in logs
+fn without_token(query: &str) -> String {
+    query
+        .split('&')
+        .filter(|pair| pair.split('=').next() != Some(auth::TOKEN_PARAM))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+impl RequestInfo {
+    /// Request path including the query string
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Value of a request header, if present and valid text
+    pub fn header(&self, name: header::HeaderName) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+}
+
+/// One access log entry (also the JSON lines format)
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    time: String,
+    remote: String,
+    method: &'a str,
+    path: &'a str,
+    status: u16,
+    bytes: Option<u64>,
+    duration_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    referer: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_agent: Option<&'a str>,
+}
+
+/// The log file behind stdout, rotated once it grows past a size limit
+///
+/// Rather than checking the file on every request, its size is tracked from
+/// the lines logged; the file is only looked at once that passes the limit.
+#[derive(Debug)]
+struct Rotation {
+    path: PathBuf,
+    max_bytes: u64,
+    /// Size of the log at the last check, plus the lines logged since
+    size: AtomicU64,
+}
+
+impl Rotation {
+    /// Count a logged line, rotating the log if it may have grown too large
+    fn logged(&self, len: u64) {
+        if self.size.fetch_add(len, Ordering::Relaxed) + len <= self.max_bytes {
+            return;
+        }
+        // Both streams go to the log: hold them so no line is written
+        // between copying the log and truncating it
+        let _stdout = std::io::stdout().lock();
+        let _stderr = std::io::stderr().lock();
+        match crate::logs::rotate_if_needed(&self.path, self.max_bytes) {
+            Ok(size) => self.size.store(size, Ordering::Relaxed),
+            Err(e) => eprintln!("Failed to rotate {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+/// Writes access log lines to stdout, which is the log file for background servers
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    format: LogFormat,
+    color: bool,
+    rotation: Option<Arc<Rotation>>,
+}
+
+impl AccessLog {
+    /// `rotation` is the log file behind stdout and its size limit
+    pub fn new(format: LogFormat, rotation: Option<(PathBuf, u64)>) -> Self {
+        Self {
+            format,
+            // Colors only make sense in a terminal (foreground mode)
+            color: std::io::stdout().is_terminal(),
+            rotation: rotation.map(|(path, max_bytes)| {
+                let size = std::fs::metadata(&path).map_or(0, |metadata| metadata.len());
+                Arc::new(Rotation {
+                    path,
+                    max_bytes,
+                    size: AtomicU64::new(size),
+                })
+            }),
+        }
+    }
+
+    /// Log a handled request
+    pub fn log(&self, info: &RequestInfo, response: &Response<Body>) {
+        if self.format == LogFormat::Off {
+            return;
+        }
+
+        // Files carry a Content-Length; for generated bodies use their size
+        let bytes = response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .or_else(|| response.body().size_hint().exact());
+        let now = OffsetDateTime::now_utc();
+        let entry = Entry {
+            time: rfc3339(now),
+            remote: info
+                .remote
+                .map(|addr| addr.ip().to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            method: info.method.as_str(),
+            path: &info.path,
+            status: response.status().as_u16(),
+            bytes,
+            duration_ms: duration_ms(info.start.elapsed()),
+            referer: info.header(header::REFERER),
+            user_agent: info.header(header::USER_AGENT),
+        };
+
+        let line = match self.format {
+            LogFormat::Json => match serde_json::to_string(&entry) {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("Failed to serialize log entry: {}", e);
+                    return;
+                }
+            },
+            LogFormat::Common => self.common_line(&entry, now),
+            LogFormat::Off => return,
+        };
+        status!("{}", line);
+
+        if let Some(ref rotation) = self.rotation {
+            rotation.logged(line.len() as u64 + 1);
+        }
+    }
+
+    /// `host ident authuser [date] "request" status bytes duration`
+    fn common_line(&self, entry: &Entry, now: OffsetDateTime) -> String {
+        let date = now
+            .format(format_description!(
+                "[day]/[month repr:short]/[year]:[hour]:[minute]:[second] +0000"
+            ))
+            .unwrap_or_default();
+        let status = entry.status.to_string();
+        let status = if !self.color {
+            status.normal()
+        } else if entry.status >= 500 {
+            status.red()
+        } else if entry.status >= 400 {
+            status.yellow()
+        } else if entry.status >= 300 {
+            status.cyan()
+        } else {
+            status.green()
+        };
+        let bytes = entry
+            .bytes
+            .map_or_else(|| "-".to_string(), |b| b.to_string());
+        format!(
+            "{} - - [{}] \"{} {}\" {} {} {:.1}ms",
+            entry.remote, date, entry.method, entry.path, status, bytes, entry.duration_ms
+        )
+    }
+}
+
+fn duration_ms(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 1_000_000.0).round() / 1000.0
+}
@@ -0,0 +1,399 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::MultiGzDecoder;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use warp::http::{header, HeaderMap, Response, StatusCode};
+use warp::hyper::Body;
+use warp::path::FullPath;
+use warp::{Filter, Rejection};
+
+use crate::{files, pages};
+
+/// Characters escaped in the links of directory listings
+const LINK: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?');
+
+/// Archive formats a site can be served from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    /// The format of an archive, from its file name
+    pub fn of(path: &Path) -> Option<ArchiveKind> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `path` is an archive file to serve the site from (`-r site.zip`)
+pub fn is_archive(path: &Path) -> bool {
+    path.is_file() && ArchiveKind::of(path).is_some()
+}
+
+/// Where an entry's content is
+#[derive(Debug, Clone, Copy)]
+enum Location {
+    /// Index in the zip's central directory
+    Zip(usize),
+    /// Offset of the (uncompressed) content in the tar stream
+    Tar(u64),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    location: Location,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+/// The open archive file
+enum Reader {
+    Zip(zip::ZipArchive<File>),
+    /// A tar file, or a tar.gz decompressed once into an unnamed temporary file
+    Tar(File),
+}
+
+/// A site served straight from a zip, tar or tar.gz archive
+///
+/// Entries are indexed in memory when the server starts; their content is
+/// read from the archive on request. A tar.gz can't be read at an offset,
+/// so it is decompressed once into a temporary tar file (not unpacked).
+/// When all entries are inside a single folder, that folder is the site
+/// root.
+pub struct Archive {
+    /// Entries by path (without leading slash)
+    entries: BTreeMap<String, Entry>,
+    /// Directories, explicit or implied by entry paths (without slashes)
+    dirs: BTreeSet<String>,
+    reader: Mutex<Reader>,
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> Result<Self> {
+        let Some(kind) = ArchiveKind::of(path) else {
+            bail!("{} is not a .zip, .tar, .tar.gz or .tgz archive", path.display());
+        };
+        let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut archive = match kind {
+            ArchiveKind::Zip => Self::open_zip(file),
+            ArchiveKind::Tar => Self::open_tar(file),
+            ArchiveKind::TarGz => Self::open_tar(decompress(file)?),
+        }
+        .with_context(|| format!("Failed to read archive {}", path.display()))?;
+        archive.strip_single_folder();
+        Ok(archive)
+    }
+
+    fn open_zip(file: File) -> Result<Self> {
+        let mut zip = zip::ZipArchive::new(file)?;
+        let mut entries = BTreeMap::new();
+        let mut dirs = BTreeSet::new();
+        for index in 0..zip.len() {
+            let entry = zip.by_index_raw(index)?;
+            let Some(name) = entry_path(entry.name()) else {
+                continue;
+            };
+            if entry.is_dir() {
+                dirs.insert(name);
+                continue;
+            }
+            let modified = zip_time(entry.last_modified());
+            entries.insert(
+                name,
+                Entry {
+                    location: Location::Zip(index),
+                    size: entry.size(),
+                    modified,
+                },
+            );
+        }
+        Ok(Self::new(entries, dirs, Reader::Zip(zip)))
+    }
+
+    fn open_tar(mut file: File) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+        let mut dirs = BTreeSet::new();
+        {
+            let mut tar = tar::Archive::new(&mut file);
+            for entry in tar.entries()? {
+                let entry = entry?;
+                let raw_path = entry.path()?.to_string_lossy().into_owned();
+                let Some(name) = entry_path(&raw_path) else {
+                    continue;
+                };
+                let header = entry.header();
+                if header.entry_type().is_dir() {
+                    dirs.insert(name);
+                } else if header.entry_type().is_file() {
+                    let modified = header
+                        .mtime()
+                        .ok()
+                        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+                    entries.insert(
+                        name,
+                        Entry {
+                            location: Location::Tar(entry.raw_file_position()),
+                            size: entry.size(),
+                            modified,
+                        },
+                    );
+                }
+                // Links and special files aren't served
+            }
+        }
+        Ok(Self::new(entries, dirs, Reader::Tar(file)))
+    }
+
+    fn new(entries: BTreeMap<String, Entry>, mut dirs: BTreeSet<String>, reader: Reader) -> Self {
+        for name in entries.keys() {
+            let mut parent = name.as_str();
+            while let Some((dir, _)) = parent.rsplit_once('/') {
+                dirs.insert(dir.to_string());
+                parent = dir;
+            }
+        }
+        Self {
+            entries,
+            dirs,
+            reader: Mutex::new(reader),
+        }
+    }
+
+    /// Serve `site/` of an archive holding only `site/...` as the root
+    fn strip_single_folder(&mut self) {
+        if self.entries.contains_key("index.html") {
+            return;
+        }
+        let mut tops = self
+            .entries
+            .keys()
+            .map(|name| name.split_once('/').map(|(top, _)| top));
+        let Some(Some(top)) = tops.next() else {
+            return;
+        };
+        let top = top.to_string();
+        if !tops.all(|t| t == Some(top.as_str())) {
+            return;
+        }
+        let prefix = format!("{}/", top);
+        self.entries = std::mem::take(&mut self.entries)
+            .into_iter()
+            .filter_map(|(name, entry)| Some((name.strip_prefix(&prefix)?.to_string(), entry)))
+            .collect();
+        self.dirs = std::mem::take(&mut self.dirs)
+            .into_iter()
+            .filter_map(|dir| dir.strip_prefix(&prefix).map(str::to_string))
+            .collect();
+    }
+
+    /// Read `len` bytes at `offset` of an entry's content
+    fn read(&self, entry: &Entry, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let mut reader = self
+            .reader
+            .lock()
+            .map_err(|_| io::Error::other("archive reader poisoned"))?;
+        // Not preallocated: `len` comes from the archive, which may lie
+        let mut contents = Vec::new();
+        match (&mut *reader, entry.location) {
+            (Reader::Zip(zip), Location::Zip(index)) => {
+                // Compressed entries can only be read from the start
+                let mut file = zip.by_index(index).map_err(io::Error::other)?;
+                io::copy(&mut (&mut file).take(offset), &mut io::sink())?;
+                file.take(len).read_to_end(&mut contents)?;
+            }
+            (Reader::Tar(file), Location::Tar(start)) => {
+                file.seek(SeekFrom::Start(start + offset))?;
+                file.take(len).read_to_end(&mut contents)?;
+            }
+            _ => return Err(io::Error::other("entry doesn't belong to this archive")),
+        }
+        Ok(contents)
+    }
+
+    /// Respond to a request path: a file, a directory's index page or
+    /// listing, or a redirect adding the trailing slash to a directory
+    fn respond(&self, path: &str, headers: &HeaderMap) -> Option<Response<Body>> {
+        let decoded = percent_decode_str(path).decode_utf8_lossy();
+        let name = decoded.trim_matches('/');
+        if let Some(entry) = self.entries.get(name) {
+            return Some(self.entry_response(name, entry, path, headers));
+        }
+        let is_dir = name.is_empty() || self.dirs.contains(name);
+        if !is_dir {
+            return None;
+        }
+        if !path.ends_with('/') {
+            // Relative links in the page need the slash
+            return Response::builder()
+                .status(StatusCode::MOVED_PERMANENTLY)
+                .header(header::LOCATION, format!("{}/", path))
+                .body(Body::empty())
+                .ok();
+        }
+        let index = if name.is_empty() {
+            "index.html".to_string()
+        } else {
+            format!("{}/index.html", name)
+        };
+        match self.entries.get(&index) {
+            Some(entry) => Some(self.entry_response(&index, entry, path, headers)),
+            None => Some(self.listing(name)),
+        }
+    }
+
+    fn entry_response(
+        &self,
+        name: &str,
+        entry: &Entry,
+        path: &str,
+        headers: &HeaderMap,
+    ) -> Response<Body> {
+        let content_type = files::content_type(Path::new(name), path);
+        files::ranged_response(headers, content_type, entry.size, entry.modified, |offset, len| {
+            self.read(entry, offset, len)
+        })
+    }
+
+    /// Listing of a directory without index page
+    fn listing(&self, dir: &str) -> Response<Body> {
+        let prefix = if dir.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", dir)
+        };
+        let children = |names: Vec<&String>| -> Vec<String> {
+            names
+                .into_iter()
+                .filter_map(|name| name.strip_prefix(&prefix))
+                .filter(|rest| !rest.is_empty() && !rest.contains('/'))
+                .map(str::to_string)
+                .collect()
+        };
+        let subdirs = children(self.dirs.iter().collect());
+        let files: Vec<(String, u64)> = children(self.entries.keys().collect())
+            .into_iter()
+            .map(|name| {
+                let size = self.entries[&format!("{}{}", prefix, name)].size;
+                (name, size)
+            })
+            .collect();
+
+        let mut items = Vec::new();
+        if !dir.is_empty() {
+            items.push("<li><a href=\"../\">../</a></li>".to_string());
+        }
+        for name in &subdirs {
+            items.push(format!(
+                "<li><a href=\"{}/\">{}/</a></li>",
+                utf8_percent_encode(name, LINK),
+                pages::escape(name)
+            ));
+        }
+        for (name, size) in &files {
+            items.push(format!(
+                "<li><a href=\"{}\">{}</a> <span class=\"muted\">({} bytes)</span></li>",
+                utf8_percent_encode(name, LINK),
+                pages::escape(name),
+                size
+            ));
+        }
+        let body = if subdirs.is_empty() && files.is_empty() {
+            "<p>This folder is empty.</p>".to_string()
+        } else {
+            format!("<ul>\n{}\n</ul>", items.join("\n"))
+        };
+        pages::html(&format!("Index of /{}", prefix), &body)
+    }
+}
+
+/// Filter serving the site from an archive
+///
+/// Rejects with a 404 for paths not in the archive.
+pub fn filter(
+    archive: Arc<Archive>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    warp::path::full()
+        .and(warp::header::headers_cloned())
+        .and_then(move |path: FullPath, headers: HeaderMap| {
+            let archive = archive.clone();
+            async move {
+                // Reading blocks on the file (and the archive lock)
+                let path = path.as_str().to_string();
+                tokio::task::spawn_blocking(move || archive.respond(&path, &headers))
+                    .await
+                    .ok()
+                    .flatten()
+                    .ok_or_else(warp::reject::not_found)
+            }
+        })
+}
+
+/// Decompress a gzip file (tar.gz, or a WARC's gzip members) into an
+/// unnamed temporary file
+pub fn decompress(file: File) -> Result<File> {
+    let temp_path = std::env::temp_dir().join(format!(
+        "hopen-archive-{}-{}",
+        std::process::id(),
+        rand::random::<u32>()
+    ));
+    let mut temp = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .with_context(|| format!("Failed to create {}", temp_path.display()))?;
+    // The open handle keeps the data; nothing is left behind on exit
+    let _ = fs::remove_file(&temp_path);
+    io::copy(&mut MultiGzDecoder::new(file), &mut temp).context("Failed to decompress")?;
+    temp.seek(SeekFrom::Start(0))?;
+    Ok(temp)
+}
+
+/// Path of an entry as served: relative, without `.` or empty segments
+///
+/// Entries reaching outside the archive (`..`, absolute paths) are skipped.
+fn entry_path(raw: &str) -> Option<String> {
+    let mut segments = Vec::new();
+    for component in Path::new(raw).components() {
+        match component {
+            Component::Normal(segment) => segments.push(segment.to_str()?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!segments.is_empty()).then(|| segments.join("/"))
+}
+
+/// A zip timestamp (local time without zone, taken as UTC)
+fn zip_time(time: zip::DateTime) -> Option<SystemTime> {
+    let month = time::Month::try_from(time.month()).ok()?;
+    let date = time::Date::from_calendar_date(time.year().into(), month, time.day()).ok()?;
+    let datetime = date
+        .with_hms(time.hour(), time.minute(), time.second())
+        .ok()?
+        .assume_utc();
+    Some(datetime.into())
+}
@@ -0,0 +1,218 @@
+use base64::Engine;
+use rand::Rng;
+use std::sync::Arc;
+use warp::http::{header, HeaderMap, Response, StatusCode};
+use warp::hyper::Body;
+use warp::{Filter, Rejection};
+
+/// Environment variable used to hand the access token to the background server
+/// (kept off the command line so it doesn't show up in `ps`)
+pub const ACCESS_TOKEN_ENV: &str = "HOPEN_ACCESS_TOKEN";
+
+/// Environment variable used to hand `--auth` credentials to the background
+/// server, for the same reason
+pub const CREDENTIALS_ENV: &str = "HOPEN_AUTH";
+
+/// Query parameter carrying the access token in the printed URL
+pub const TOKEN_PARAM: &str = "token";
+
+/// The access token in a URL printed by a server, if any
+pub fn token_in_url(url: &str) -> Option<String> {
+    let (_, query) = url.split_once('?')?;
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(TOKEN_PARAM)?.strip_prefix('='))
+        .map(str::to_string)
+}
+
+/// How requests must authenticate
+#[derive(Debug, Clone)]
+pub enum Auth {
+    /// Anyone who can reach the server may read it
+    Open,
+    /// HTTP basic auth; holds the expected `Authorization` header value
+    Basic { expected: String },
+    /// Random token: accepted once from the URL, then kept in a cookie
+    Token {
+        token: String,
+        cookie_name: String,
+        secure: bool,
+    },
+}
+
+impl Auth {
+    /// Basic auth for `user:pass` credentials
+    pub fn basic(credentials: &str) -> Self {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+        Auth::Basic {
+            expected: format!("Basic {}", encoded),
+        }
+    }
+
+    /// Token auth; the cookie is named per port since cookies ignore ports
+    pub fn token(token: String, port: u16, secure: bool) -> Self {
+        Auth::Token {
+            token,
+            cookie_name: format!("hopen_token_{}", port),
+            secure,
+        }
+    }
+
+    /// Check a request, rejecting it with `Unauthorized` or `TokenAccepted`
+    fn check(&self, path: &str, query: &str, headers: &HeaderMap) -> Result<(), Rejection> {
+        match self {
+            Auth::Open => Ok(()),
+            Auth::Basic { expected } => {
+                let given = headers
+                    .get(header::AUTHORIZATION)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or("");
+                if constant_time_eq(given, expected) {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(Unauthorized { basic: true }))
+                }
+            }
+            Auth::Token {
+                token,
+                cookie_name,
+                secure,
+            } => {
+                if cookie_value(headers, cookie_name).is_some_and(|v| constant_time_eq(v, token)) {
+                    return Ok(());
+                }
+
+                // Token in the URL: set the cookie and redirect to the same URL
+                // without it, so it doesn't linger in history or referrers
+                let (given, rest) = split_token_param(query);
+                if given.is_some_and(|v| constant_time_eq(&v, token)) {
+                    let location = if rest.is_empty() {
+                        path.to_string()
+                    } else {
+                        format!("{}?{}", path, rest)
+                    };
+                    let mut cookie = format!(
+                        "{}={}; Path=/; HttpOnly; SameSite=Lax",
+                        cookie_name, token
+                    );
+                    if *secure {
+                        cookie.push_str("; Secure");
+                    }
+                    return Err(warp::reject::custom(TokenAccepted { location, cookie }));
+                }
+
+                Err(warp::reject::custom(Unauthorized { basic: false }))
+            }
+        }
+    }
+}
+
+/// Filter that lets a request through only if it is authenticated
+pub fn filter(auth: Arc<Auth>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    let raw_query = warp::query::raw()
+        .or(warp::any().map(String::new))
+        .unify();
+
+    warp::path::full()
+        .and(raw_query)
+        .and(warp::header::headers_cloned())
+        .and_then(move |path: warp::path::FullPath, query: String, headers: HeaderMap| {
+            let auth = auth.clone();
+            async move { auth.check(path.as_str(), &query, &headers) }
+        })
+        .untuple_one()
+}
+
+/// Rejection: the request lacks valid credentials
+#[derive(Debug)]
+pub struct Unauthorized {
+    basic: bool,
+}
+
+impl warp::reject::Reject for Unauthorized {}
+
+impl Unauthorized {
+    pub fn response(&self) -> Response<Body> {
+        let mut response = Response::builder().status(StatusCode::UNAUTHORIZED);
+        let body = if self.basic {
+            response = response.header(header::WWW_AUTHENTICATE, "Basic realm=\"hopen\"");
+            "401 Unauthorized\n"
+        } else {
+            "401 Unauthorized: open the URL printed by hopen (it includes the access token)\n"
+        };
+        response
+            .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(Body::from(body))
+            .unwrap_or_default()
+    }
+}
+
+/// Rejection: a valid token was given in the URL, so set the cookie and redirect
+#[derive(Debug)]
+pub struct TokenAccepted {
+    location: String,
+    cookie: String,
+}
+
+impl warp::reject::Reject for TokenAccepted {}
+
+impl TokenAccepted {
+    pub fn response(&self) -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::FOUND)
+            .header(header::LOCATION, &self.location)
+            .header(header::SET_COOKIE, &self.cookie)
+            .body(Body::empty())
+            .unwrap_or_default()
+    }
+}
+
+/// Generate a random access token (128 bits, hex encoded)
+pub fn generate_token() -> String {
+    let bytes: [u8; 16] = rand::thread_rng().gen();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Validate a `USER:PASS` command-line value
+pub fn parse_credentials(value: &str) -> Result<String, String> {
+    match value.split_once(':') {
+        Some((user, _)) if !user.is_empty() => Ok(value.to_string()),
+        _ => Err("expected USER:PASS".to_string()),
+    }
+}
+
+/// Value of a cookie in the request headers
+fn cookie_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v)
+}
+
+/// Split the token parameter out of a raw query string
+///
+/// Returns the token (if present) and the remaining query.
+fn split_token_param(query: &str) -> (Option<String>, String) {
+    let mut token = None;
+    let mut rest = Vec::new();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        match pair.split_once('=') {
+            Some((TOKEN_PARAM, value)) => token = Some(value.to_string()),
+            _ => rest.push(pair),
+        }
+    }
+    (token, rest.join("&"))
+}
+
+/// Compare secrets without leaking how much of them matched through timing
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
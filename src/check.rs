@@ -0,0 +1,430 @@
+use anyhow::{bail, Result};
+use colored::*;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::domains::{DomainMode, Domains};
+use crate::fallback::FallbackResolver;
+use crate::html;
+use crate::mirror::MirrorNames;
+use crate::policy::{Denial, ServePolicy};
+use crate::rewrite::OriginRewriter;
+use crate::server::ServeArgs;
+
+/// Characters escaped when turning file names back into URL paths
+const SEGMENT: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b'#').add(b'%').add(b'?');
+
+/// Arguments of `hopen check`
+#[derive(clap::Args, Debug)]
+pub struct CheckArgs {
+    /// Site root to check. Defaults to HOPEN_SITE_HOME, then the current
+    /// directory.
+    #[arg(value_name = "ROOT")]
+    pub root: Option<PathBuf>,
+}
+
+/// What is wrong with a reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ProblemKind {
+    /// Nothing is served at the URL
+    #[serde(rename = "broken")]
+    Broken,
+    /// The page exists, but has no element with the fragment as id
+    #[serde(rename = "missing-anchor")]
+    MissingAnchor,
+    /// The URL leaves the site root (`../` too many, a `file:` URL or a
+    /// symlink pointing outside)
+    #[serde(rename = "outside-root")]
+    OutsideRoot,
+}
+
+impl ProblemKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProblemKind::Broken => "broken link",
+            ProblemKind::MissingAnchor => "missing anchor",
+            ProblemKind::OutsideRoot => "outside root",
+        }
+    }
+}
+
+/// A reference that doesn't work when the site is served
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    /// URL path of the page or stylesheet
+    pub page: String,
+    pub line: usize,
+    /// The reference as written
+    pub url: String,
+    pub kind: ProblemKind,
+}
+
+/// Result of `hopen check`
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckReport {
+    pub root: PathBuf,
+    /// HTML pages and stylesheets read
+    pub files: usize,
+    /// Internal references checked
+    pub links: usize,
+    pub problems: Vec<Problem>,
+}
+
+/// Where a URL path leads
+enum Resolution {
+    File(PathBuf),
+    Missing,
+    Outside,
+}
+
+/// Finds the files URLs are served from, the way `run_server` does
+///
+/// The serve policy comes first (hidden files are missing, symlinks out of
+/// the root are outside it), then the names mirroring tools give URLs with
+/// a query string, the file itself (a directory's `index.html`), and last
+/// the case and Unicode fixups when enabled.
+struct SiteResolver {
+    root: PathBuf,
+    policy: ServePolicy,
+    mirror_names: MirrorNames,
+    fallback: Option<FallbackResolver>,
+}
+
+impl SiteResolver {
+    fn new(root: &Path, serve: &ServeArgs) -> Self {
+        let fallback = (serve.ignore_case || serve.normalize_unicode)
+            .then(|| FallbackResolver::new(serve.ignore_case, serve.normalize_unicode, None));
+        Self {
+            root: root.to_path_buf(),
+            policy: ServePolicy::new(root, &serve.allow_dotfile, serve.follow_symlinks),
+            mirror_names: MirrorNames::new(root, &serve.mirror_schemes()),
+            fallback,
+        }
+    }
+
+    /// Resolve a path, given as decoded segments, and a raw query
+    fn resolve(&self, segments: &[String], is_dir: bool, query: &str) -> Resolution {
+        match self.policy.check_segments(segments) {
+            Ok(()) => {}
+            Err(Denial::Hidden) => return Resolution::Missing,
+            Err(Denial::OutsideRoot) => return Resolution::Outside,
+        }
+        let mut path: String = segments
+            .iter()
+            .map(|s| format!("/{}", utf8_percent_encode(s, SEGMENT)))
+            .collect();
+        if is_dir || path.is_empty() {
+            path.push('/');
+        }
+
+        if !query.is_empty() {
+            if let Some(file) = self.mirror_names.resolve(&self.root, &self.policy, &path, query) {
+                return Resolution::File(self.root.join(file));
+            }
+        }
+        let mut file = self.root.clone();
+        file.extend(segments);
+        let is_index = file.is_dir();
+        if is_index {
+            file.push("index.html");
+        }
+        // `page.html/` isn't served
+        if file.is_file() && (is_index || !is_dir) {
+            return Resolution::File(file);
+        }
+        if let Some(ref fallback) = self.fallback {
+            if let Some((found, _)) = fallback.resolve(&self.root, &self.policy, &path) {
+                let mut file = self.root.clone();
+                file.extend(found);
+                return Resolution::File(file);
+            }
+        }
+        Resolution::Missing
+    }
+}
+
+/// A page or stylesheet being checked
+struct Source {
+    path: PathBuf,
+    /// URL path segments of the file, decoded
+    segments: Vec<String>,
+    /// Directory relative references are resolved against (from `<base>`)
+    dir: Vec<String>,
+}
+
+/// Checks references, remembering the anchors of the pages seen
+struct Checker {
+    resolver: SiteResolver,
+    rewriter: OriginRewriter,
+    domains: Option<Domains>,
+    anchors: HashMap<PathBuf, HashSet<String>>,
+}
+
+/// Check the links of every page and stylesheet under `root`
+///
+/// Absolute links to the `--rewrite-origin` origins (and, with `--domains`,
+/// to the mirrored domains) count as internal, as they are rewritten when
+/// served. Other absolute URLs aren't checked.
+pub fn check(root: &Path, serve: &ServeArgs) -> Result<CheckReport> {
+    if !root.is_dir() {
+        bail!("{} is not a directory", root.display());
+    }
+    let root = root.canonicalize()?;
+    let mut rewriter = OriginRewriter::new(&serve.rewrite_origin);
+    let domains = serve.domains.map(|_| Domains::new(DomainMode::Prefix, &root, "http", 0));
+    if let Some(ref domains) = domains {
+        domains.add_rewrites(&mut rewriter);
+    }
+    let mut checker = Checker {
+        resolver: SiteResolver::new(&root, serve),
+        rewriter,
+        domains,
+        anchors: HashMap::new(),
+    };
+
+    let mut report = CheckReport {
+        root: root.clone(),
+        files: 0,
+        links: 0,
+        problems: Vec::new(),
+    };
+    for path in site_files(&root, &checker.resolver.policy) {
+        let Ok(text) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let Ok(relative) = path.strip_prefix(&root) else {
+            continue;
+        };
+        let segments: Vec<String> = relative
+            .iter()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect();
+        report.files += 1;
+
+        let (references, base) = if is_html(&path) {
+            let document = html::scan(&text);
+            checker.anchors.insert(path.clone(), document.anchors);
+            (document.references, document.base)
+        } else {
+            (html::css_references(&text, 0), None)
+        };
+        let mut dir = segments[..segments.len() - 1].to_vec();
+        if let Some(base) = base.map(|b| checker.rewrite(&b)) {
+            if let Some(Ok((base_segments, is_dir))) = local_path(&base, &dir) {
+                dir = base_segments;
+                if !is_dir {
+                    dir.pop();
+                }
+            }
+        }
+        let source = Source {
+            path: path.clone(),
+            segments,
+            dir,
+        };
+
+        for reference in references {
+            let Some(result) = checker.check(&reference.url, &source) else {
+                continue;
+            };
+            report.links += 1;
+            if let Err(kind) = result {
+                report.problems.push(Problem {
+                    page: format!("/{}", source.segments.join("/")),
+                    line: html::line_of(&text, reference.offset),
+                    url: reference.url,
+                    kind,
+                });
+            }
+        }
+    }
+    Ok(report)
+}
+
+impl Checker {
+    /// Check one reference of `source`; `None` when it isn't internal
+    fn check(&mut self, url: &str, source: &Source) -> Option<Result<(), ProblemKind>> {
+        let url = self.rewrite(url.trim());
+        let (url, fragment) = match url.split_once('#') {
+            Some((url, fragment)) => (url.to_string(), Some(fragment.to_string())),
+            None => (url, None),
+        };
+        if url.is_empty() && fragment.as_deref().is_none_or(str::is_empty) {
+            return None;
+        }
+        if url.to_ascii_lowercase().starts_with("file:") {
+            return Some(Err(ProblemKind::OutsideRoot));
+        }
+
+        let target = if url.is_empty() {
+            source.path.clone()
+        } else {
+            let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+            let (segments, is_dir) = match local_path(path, &source.dir)? {
+                Ok(resolved) => resolved,
+                Err(kind) => return Some(Err(kind)),
+            };
+            match self.resolve(&segments, is_dir, query, path.starts_with('/'), source) {
+                Resolution::File(target) => target,
+                Resolution::Missing => return Some(Err(ProblemKind::Broken)),
+                Resolution::Outside => return Some(Err(ProblemKind::OutsideRoot)),
+            }
+        };
+
+        // `#` and `#top` always work; other fragments need an id in an HTML page
+        let Some(fragment) = fragment.filter(|f| !f.is_empty() && !f.eq_ignore_ascii_case("top"))
+        else {
+            return Some(Ok(()));
+        };
+        if !is_html(&target) {
+            return Some(Ok(()));
+        }
+        let ids = self.anchors.entry(target.clone()).or_insert_with(|| {
+            fs::read_to_string(&target)
+                .map(|text| html::scan(&text).anchors)
+                .unwrap_or_default()
+        });
+        let fragment = percent_decode_str(&fragment).decode_utf8_lossy();
+        if ids.contains(fragment.as_ref()) {
+            Some(Ok(()))
+        } else {
+            Some(Err(ProblemKind::MissingAnchor))
+        }
+    }
+
+    fn resolve(
+        &self,
+        segments: &[String],
+        is_dir: bool,
+        query: &str,
+        root_relative: bool,
+        source: &Source,
+    ) -> Resolution {
+        let resolution = self.resolver.resolve(segments, is_dir, query);
+        // With --domains, root-relative links from a domain's pages stay in
+        // its folder (the server redirects them by Referer)
+        let domain = self
+            .domains
+            .as_ref()
+            .and_then(|domains| source.segments.first().filter(|d| domains.names().contains(d)));
+        match (resolution, domain) {
+            (Resolution::Missing, Some(domain)) if root_relative => {
+                let mut in_domain = vec![domain.clone()];
+                in_domain.extend(segments.iter().cloned());
+                self.resolver.resolve(&in_domain, is_dir, query)
+            }
+            (resolution, _) => resolution,
+        }
+    }
+
+    /// Rewrite absolute URLs of the mirrored origins to local paths
+    fn rewrite(&self, url: &str) -> String {
+        String::from_utf8_lossy(&self.rewriter.rewrite(url.as_bytes())).into_owned()
+    }
+}
+
+/// Decoded path segments of a URL path, resolved against `dir`, and whether
+/// it names a directory
+///
+/// `None` for URLs that aren't local (another scheme or host), an error for
+/// paths climbing above the root.
+fn local_path(path: &str, dir: &[String]) -> Option<Result<(Vec<String>, bool), ProblemKind>> {
+    let is_external = path.starts_with("//")
+        || path
+            .split_once(':')
+            .is_some_and(|(scheme, _)| !scheme.is_empty() && !scheme.contains('/'));
+    if is_external {
+        return None;
+    }
+    let mut segments = if path.starts_with('/') {
+        Vec::new()
+    } else {
+        dir.to_vec()
+    };
+    let parts: Vec<&str> = path.split('/').collect();
+    for part in &parts {
+        match *part {
+            "" | "." => {}
+            ".." => {
+                if segments.pop().is_none() {
+                    return Some(Err(ProblemKind::OutsideRoot));
+                }
+            }
+            _ => segments.push(percent_decode_str(part).decode_utf8_lossy().into_owned()),
+        }
+    }
+    let is_dir = path.is_empty() || matches!(parts.last(), Some(&"" | &"." | &".."));
+    Some(Ok((segments, is_dir)))
+}
+
+/// The HTML pages and stylesheets under `root` that may be served
+pub fn site_files(root: &Path, policy: &ServePolicy) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    let mut dirs = vec![(root.to_path_buf(), Vec::<String>::new())];
+    while let Some((dir, segments)) = dirs.pop() {
+        // Symlinks may lead back up the tree
+        if !visited.insert(dir.canonicalize().unwrap_or_else(|_| dir.clone())) {
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let mut entry_segments = segments.clone();
+            entry_segments.push(name);
+            if policy.check_segments(&entry_segments).is_err() {
+                continue;
+            }
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push((path, entry_segments));
+            } else if is_html(&path) || is_css(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+pub fn is_html(path: &Path) -> bool {
+    let name = path.to_string_lossy().to_ascii_lowercase();
+    name.ends_with(".html") || name.ends_with(".htm") || name.ends_with(".xhtml")
+}
+
+fn is_css(path: &Path) -> bool {
+    path.to_string_lossy().to_ascii_lowercase().ends_with(".css")
+}
+
+/// Print a report for `hopen check`
+pub fn print(report: &CheckReport) {
+    println!(
+        "{} {}",
+        format!(
+            "Checked {} links in {} files under",
+            report.links, report.files
+        )
+        .bold(),
+        report.root.display().to_string().magenta()
+    );
+    if report.problems.is_empty() {
+        println!("{}", "✓ No broken links".green());
+        return;
+    }
+    for problem in &report.problems {
+        println!(
+            "{}  {}  {}",
+            format!("{}:{}", problem.page, problem.line).cyan(),
+            problem.kind.label().yellow(),
+            problem.url
+        );
+    }
+    let count = report.problems.len();
+    let noun = if count == 1 { "problem" } else { "problems" };
+    println!("{}", format!("✗ {} {} found", count, noun).red());
+}
@@ -0,0 +1,196 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use warp::http::{header, Response, StatusCode};
+use warp::hyper::Body;
+
+use crate::pages;
+use crate::policy::ServePolicy;
+use crate::rewrite::OriginRewriter;
+
+/// How the domain folders of a multi-domain mirror are served
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DomainMode {
+    /// Each domain under its folder name: /cdn.example.com/...
+    Prefix,
+    /// Each domain on its own host: cdn.example.com.localhost
+    Vhost,
+}
+
+impl DomainMode {
+    pub fn as_arg(&self) -> &'static str {
+        match self {
+            DomainMode::Prefix => "prefix",
+            DomainMode::Vhost => "vhost",
+        }
+    }
+}
+
+/// The domain folders of a mirror root (`www.example.com/`, `cdn.example.com/`)
+/// and where each one is served locally
+#[derive(Debug, Clone)]
+pub struct Domains {
+    mode: DomainMode,
+    root: PathBuf,
+    names: Vec<String>,
+    scheme: &'static str,
+    port: u16,
+}
+
+impl Domains {
+    pub fn new(mode: DomainMode, root: &Path, scheme: &'static str, port: u16) -> Self {
+        Self {
+            mode,
+            root: root.to_path_buf(),
+            names: domain_dirs(root),
+            scheme,
+            port,
+        }
+    }
+
+    pub fn mode(&self) -> DomainMode {
+        self.mode
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Folder holding a domain's files
+    pub fn dir(&self, domain: &str) -> PathBuf {
+        self.root.join(domain)
+    }
+
+    /// Local address of a domain: a path prefix, or an origin for vhosts
+    /// (without trailing slash)
+    pub fn local_base(&self, domain: &str) -> String {
+        match self.mode {
+            DomainMode::Prefix => format!("/{}", domain),
+            DomainMode::Vhost => {
+                format!("{}://{}.localhost:{}", self.scheme, domain, self.port)
+            }
+        }
+    }
+
+    /// Point links between the mirrored domains at their local copies
+    pub fn add_rewrites(&self, rewriter: &mut OriginRewriter) {
+        for name in &self.names {
+            rewriter.add(name, &self.local_base(name));
+        }
+    }
+
+    /// The domain a vhost name refers to (`cdn.example.com.localhost`)
+    pub fn for_host(&self, host: &str) -> Option<&str> {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let domain = host.strip_suffix(".localhost")?;
+        self.names
+            .iter()
+            .find(|name| name.eq_ignore_ascii_case(domain))
+            .map(String::as_str)
+    }
+
+    /// Domain folder a page belongs to, from its URL path (prefix mode)
+    fn for_path<'a>(&'a self, path: &str) -> Option<&'a str> {
+        let first = path.trim_start_matches('/').split('/').next()?;
+        self.names.iter().find(|name| *name == first).map(String::as_str)
+    }
+
+    /// Redirect a root-relative request made from a domain's page into that
+    /// domain's folder (prefix mode)
+    ///
+    /// A page at `/www.example.com/index.html` linking to `/css/site.css`
+    /// means `/www.example.com/css/site.css`, which only the Referer tells us.
+    ///
+    /// The target must be one the server may serve (`policy`), or the
+    /// redirect would give away that a hidden file exists.
+    pub fn redirect_from_referer(
+        &self,
+        path: &str,
+        query: &str,
+        referer: Option<&str>,
+        policy: &ServePolicy,
+    ) -> Option<Response<Body>> {
+        if self.mode != DomainMode::Prefix || self.for_path(path).is_some() {
+            return None;
+        }
+        let referer_path = referer.map(referer_path)?;
+        let domain = self.for_path(&referer_path)?;
+        let relative = path.trim_start_matches('/');
+        let mut segments = vec![domain.to_string()];
+        for segment in relative.split('/').filter(|s| !s.is_empty()) {
+            let segment = percent_encoding::percent_decode_str(segment).decode_utf8_lossy();
+            // Only plain names, so the lookup can't leave the domain folder
+            if segment == "." || segment == ".." || segment.contains(['/', '\\']) {
+                return None;
+            }
+            segments.push(segment.into_owned());
+        }
+        let file: PathBuf = segments.iter().fold(self.root.clone(), |path, s| path.join(s));
+        if policy.check_segments(&segments).is_err() || !file.exists() {
+            return None;
+        }
+        let mut location = format!("/{}/{}", domain, relative);
+        if !query.is_empty() {
+            location.push('?');
+            location.push_str(query);
+        }
+        Response::builder()
+            .status(StatusCode::FOUND)
+            .header(header::LOCATION, location)
+            .body(Body::empty())
+            .ok()
+    }
+
+    /// List the domains (shown at `/` when the mirror root has no index page)
+    pub fn index_page(&self) -> Response<Body> {
+        let items: Vec<String> = self
+            .names
+            .iter()
+            .map(|name| {
+                format!(
+                    "<li><a href=\"{}/\">{}</a></li>",
+                    pages::escape(&self.local_base(name)),
+                    pages::escape(name)
+                )
+            })
+            .collect();
+        let body = if items.is_empty() {
+            "<p>No domain folders found.</p>".to_string()
+        } else {
+            format!("<ul>\n{}\n</ul>", items.join("\n"))
+        };
+        pages::html("Mirrored domains", &body)
+    }
+}
+
+/// Subfolders of `root` named like domains (e.g. `www.example.com`), sorted
+pub fn domain_dirs(root: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| is_domain_name(name))
+        .collect();
+    names.sort();
+    names
+}
+
+fn is_domain_name(name: &str) -> bool {
+    name.contains('.')
+        && !name.starts_with('.')
+        && !name.ends_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+}
+
+/// Path part of a Referer URL
+fn referer_path(referer: &str) -> String {
+    let without_scheme = referer.split_once("://").map_or(referer, |(_, rest)| rest);
+    match without_scheme.find('/') {
+        Some(slash) => without_scheme[slash..].to_string(),
+        None => "/".to_string(),
+    }
+}
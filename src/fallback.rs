@@ -0,0 +1,188 @@
+use percent_encoding::percent_decode_str;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use unicode_normalization::UnicodeNormalization;
+use warp::http::{HeaderMap, Response};
+use warp::hyper::Body;
+use warp::path::FullPath;
+use warp::{Filter, Rejection};
+
+use crate::files;
+use crate::fixups::{self, FixupKind, FixupReport};
+use crate::policy::ServePolicy;
+
+/// A directory's entries by folded name, with its modification time
+type DirIndex = (Option<SystemTime>, Arc<HashMap<String, String>>);
+
+/// Finds files whose name differs from the request in letter case or
+/// Unicode normalization
+///
+/// Mirrors captured on a case-insensitive file system (macOS, Windows) often
+/// link to `Images/Logo.PNG` while the file is `images/logo.png`, which only
+/// works there. Likewise macOS may store `café.html` decomposed (NFD) while
+/// links spell it composed (NFC). Used after the exact lookup failed; for
+/// case, each directory's listing is cached until the directory changes.
+#[derive(Debug)]
+pub struct FallbackResolver {
+    ignore_case: bool,
+    normalize: bool,
+    dirs: Mutex<HashMap<PathBuf, DirIndex>>,
+    /// Where served fixups are recorded (none for `hopen check`)
+    report: Option<Arc<FixupReport>>,
+}
+
+impl FallbackResolver {
+    pub fn new(ignore_case: bool, normalize: bool, report: Option<Arc<FixupReport>>) -> Self {
+        Self {
+            ignore_case,
+            normalize,
+            dirs: Mutex::new(HashMap::new()),
+            report,
+        }
+    }
+
+    /// The file (relative to `root`) a request path refers to, and what had
+    /// to be fixed to find it
+    ///
+    /// Directories resolve to their index page. Returns `None` when nothing
+    /// matches, or the match may not be served.
+    pub fn resolve(
+        &self,
+        root: &Path,
+        policy: &ServePolicy,
+        path: &str,
+    ) -> Option<(Vec<String>, FixupKind)> {
+        let mut segments: Vec<String> = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+            .collect();
+        if segments.iter().any(|s| s == "." || s == "..") {
+            return None;
+        }
+
+        let mut resolved = Vec::with_capacity(segments.len() + 1);
+        let mut kind: Option<FixupKind> = None;
+        let mut dir = root.to_path_buf();
+        if dir.join(segments.join("/")).is_dir() || path.ends_with('/') {
+            segments.push("index.html".to_string());
+        }
+        for segment in segments {
+            let name = if dir.join(&segment).exists() {
+                segment
+            } else {
+                let (name, fixup) = self.find(&dir, &segment)?;
+                kind = Some(kind.map_or(fixup, |kind| kind.and(fixup)));
+                name
+            };
+            dir.push(&name);
+            resolved.push(name);
+        }
+        if !dir.is_file() {
+            // A directory URL whose name needed fixing: serve its index page
+            let (index, fixup) = self.find(&dir, "index.html")?;
+            if index != "index.html" {
+                kind = Some(kind.map_or(fixup, |kind| kind.and(fixup)));
+            }
+            dir.push(&index);
+            resolved.push(index);
+        }
+        let kind = kind?;
+        (dir.is_file() && policy.check_segments(&resolved).is_ok()).then_some((resolved, kind))
+    }
+
+    /// Real name of the entry of `dir` that `name` refers to, and the fixup
+    fn find(&self, dir: &Path, name: &str) -> Option<(String, FixupKind)> {
+        if self.normalize {
+            let variants = [name.nfc().collect::<String>(), name.nfd().collect()];
+            if let Some(found) = variants.into_iter().find(|v| v != name && dir.join(v).exists()) {
+                return Some((found, FixupKind::Unicode));
+            }
+        }
+        if self.ignore_case {
+            let found = self.index(dir)?.get(&self.fold(name)).cloned()?;
+            // Also normalized if it still differs once lowercased
+            let kind = if found.to_lowercase() == name.to_lowercase() {
+                FixupKind::Case
+            } else {
+                FixupKind::CaseAndUnicode
+            };
+            return Some((found, kind));
+        }
+        None
+    }
+
+    /// Key of a name in the directory index
+    fn fold(&self, name: &str) -> String {
+        if self.normalize {
+            name.nfc().collect::<String>().to_lowercase()
+        } else {
+            name.to_lowercase()
+        }
+    }
+
+    fn index(&self, dir: &Path) -> Option<Arc<HashMap<String, String>>> {
+        let modified = fs::metadata(dir).ok()?.modified().ok();
+        let mut dirs = self.dirs.lock().ok()?;
+        if let Some((cached_modified, index)) = dirs.get(dir) {
+            if *cached_modified == modified {
+                return Some(index.clone());
+            }
+        }
+
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .ok()?
+            .flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        // Of names differing only in case, the first in sort order wins
+        names.sort();
+        let mut index = HashMap::new();
+        for name in names {
+            index.entry(self.fold(&name)).or_insert(name);
+        }
+        let index = Arc::new(index);
+        dirs.insert(dir.to_path_buf(), (modified, index.clone()));
+        Some(index)
+    }
+}
+
+/// Filter serving requests that only match a file once their case or
+/// normalization is fixed, logging and reporting each fixup
+///
+/// Rejects with a 404 when nothing matches.
+pub fn filter(
+    root: &Path,
+    resolver: Arc<FallbackResolver>,
+    policy: Arc<ServePolicy>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let root = Arc::new(root.to_path_buf());
+    warp::path::full()
+        .and(warp::header::headers_cloned())
+        .and_then(move |path: FullPath, headers: HeaderMap| {
+            let root = root.clone();
+            let resolver = resolver.clone();
+            let policy = policy.clone();
+            async move {
+                let Some((segments, kind)) = resolver.resolve(&root, &policy, path.as_str())
+                else {
+                    return Err(warp::reject::not_found());
+                };
+                let file = format!("/{}", segments.join("/"));
+                let label = match kind {
+                    FixupKind::Case => "Case",
+                    FixupKind::Unicode => "Unicode",
+                    FixupKind::CaseAndUnicode => "Case and Unicode",
+                };
+                eprintln!("{} fixup: {} → {}", label, path.as_str(), file);
+                if let Some(ref report) = resolver.report {
+                    fixups::record(report, path.as_str(), &file, kind);
+                }
+                let local = root.join(segments.join("/"));
+                Ok(files::file_response(&local, path.as_str(), &headers).await)
+            }
+        })
+}
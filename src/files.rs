@@ -0,0 +1,193 @@
+use std::path::Path;
+use std::time::SystemTime;
+use warp::http::{header, HeaderMap, HeaderValue, Response, StatusCode};
+use warp::hyper::Body;
+
+/// Extensions of server-side scripts, whose mirrored output is HTML
+const SCRIPT_EXTENSIONS: &[&str] = &["php", "asp", "aspx", "jsp", "cgi", "pl", "cfm"];
+
+/// Respond with a file that `warp::fs::dir` can't find from the URL alone
+/// (e.g. a mirror's `page.php?id=3.html` for `/page.php?id=3`)
+///
+/// `request_path` is the path the file was requested as, used to guess the
+/// content type when the file name itself doesn't tell.
+pub async fn file_response(path: &Path, request_path: &str, headers: &HeaderMap) -> Response<Body> {
+    let modified = tokio::fs::metadata(path)
+        .await
+        .ok()
+        .and_then(|metadata| metadata.modified().ok());
+
+    if is_not_modified(headers, modified) {
+        return not_modified();
+    }
+
+    let contents = match tokio::fs::read(path).await {
+        Ok(contents) => contents,
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap_or_default();
+        }
+    };
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, content_type(path, request_path))
+        .header(header::CONTENT_LENGTH, contents.len());
+    if let Some(modified) = modified {
+        response = response.header(header::LAST_MODIFIED, httpdate::fmt_http_date(modified));
+    }
+    response.body(Body::from(contents)).unwrap_or_default()
+}
+
+/// A `Range: bytes=...` request against content of a known length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// No (usable) range: send everything
+    Full,
+    /// First and last byte, inclusive
+    Partial(u64, u64),
+    /// The range lies beyond the end
+    Unsatisfiable,
+}
+
+/// The single byte range a request asks for
+///
+/// Multiple ranges aren't supported and get the full content, which is
+/// allowed by the spec.
+pub fn requested_range(headers: &HeaderMap, len: u64) -> ByteRange {
+    let Some(spec) = headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().strip_prefix("bytes="))
+    else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return ByteRange::Full;
+    };
+    let (start, end) = (start.trim(), end.trim());
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // bytes=100-199
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+        // bytes=100-
+        (Ok(start), Err(_)) if end.is_empty() => (start, len.saturating_sub(1)),
+        // bytes=-500: the last 500 bytes
+        (Err(_), Ok(suffix)) if start.is_empty() && suffix > 0 => {
+            (len.saturating_sub(suffix), len.saturating_sub(1))
+        }
+        _ => return ByteRange::Full,
+    };
+    if range.0 >= len {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Partial(range.0, range.1)
+    }
+}
+
+/// Respond with content read by `read(offset, len)`, honoring Range and
+/// If-Modified-Since
+///
+/// Used for files that don't live on disk as such (archive entries), so
+/// only the requested bytes need to be read.
+pub fn ranged_response<F>(
+    headers: &HeaderMap,
+    content_type: HeaderValue,
+    len: u64,
+    modified: Option<SystemTime>,
+    read: F,
+) -> Response<Body>
+where
+    F: FnOnce(u64, u64) -> std::io::Result<Vec<u8>>,
+{
+    if is_not_modified(headers, modified) {
+        return not_modified();
+    }
+    let range = requested_range(headers, len);
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::ACCEPT_RANGES, "bytes");
+    if let Some(modified) = modified {
+        response = response.header(header::LAST_MODIFIED, httpdate::fmt_http_date(modified));
+    }
+    let (offset, count) = match range {
+        ByteRange::Full => (0, len),
+        ByteRange::Partial(start, end) => {
+            response = response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len));
+            (start, end - start + 1)
+        }
+        ByteRange::Unsatisfiable => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                .body(Body::empty())
+                .unwrap_or_default();
+        }
+    };
+    match read(offset, count) {
+        Ok(contents) => response
+            .header(header::CONTENT_LENGTH, contents.len())
+            .body(Body::from(contents))
+            .unwrap_or_default(),
+        Err(e) => {
+            eprintln!("Failed to read {} bytes at {}: {}", count, offset, e);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap_or_default()
+        }
+    }
+}
+
+/// Content type from the file name, else from the requested path
+///
+/// A saved server-side script (`page-id=3.php`) is the page it generated.
+pub fn content_type(path: &Path, request_path: &str) -> HeaderValue {
+    let request_path = Path::new(request_path);
+    let guess = if is_script(path) {
+        Some(mime_guess::mime::TEXT_HTML_UTF_8)
+    } else {
+        mime_guess::from_path(path).first().or_else(|| {
+            if is_script(request_path) {
+                Some(mime_guess::mime::TEXT_HTML_UTF_8)
+            } else {
+                mime_guess::from_path(request_path).first()
+            }
+        })
+    };
+    let mime = guess.unwrap_or(mime_guess::mime::APPLICATION_OCTET_STREAM);
+    HeaderValue::from_str(mime.as_ref())
+        .unwrap_or_else(|_| HeaderValue::from_static("application/octet-stream"))
+}
+
+fn is_script(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SCRIPT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Whether the client's copy (If-Modified-Since) is still current
+fn is_not_modified(headers: &HeaderMap, modified: Option<SystemTime>) -> bool {
+    match (modified, if_modified_since(headers)) {
+        (Some(modified), Some(since)) => httpdate::HttpDate::from(modified) <= since,
+        _ => false,
+    }
+}
+
+fn not_modified() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_MODIFIED)
+        .body(Body::empty())
+        .unwrap_or_default()
+}
+
+fn if_modified_since(headers: &HeaderMap) -> Option<httpdate::HttpDate> {
+    let value = headers.get(header::IF_MODIFIED_SINCE)?.to_str().ok()?;
+    let time: SystemTime = httpdate::parse_http_date(value).ok()?;
+    Some(time.into())
+}
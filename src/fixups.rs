@@ -0,0 +1,164 @@
+use anyhow::Result;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::reports::{self, ReportSnapshot, ReportStore};
+use crate::{access_log, pages};
+
+/// How a request path had to be changed to find its file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FixupKind {
+    /// Letter case (--ignore-case)
+    #[serde(rename = "case")]
+    Case,
+    /// Unicode normalization, NFC or NFD (--normalize-unicode)
+    #[serde(rename = "unicode")]
+    Unicode,
+    /// Both
+    #[serde(rename = "case+unicode")]
+    CaseAndUnicode,
+}
+
+impl FixupKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FixupKind::Case => "case",
+            FixupKind::Unicode => "unicode",
+            FixupKind::CaseAndUnicode => "case+unicode",
+        }
+    }
+
+    /// Combine the fixups of two path segments
+    pub fn and(self, other: FixupKind) -> FixupKind {
+        if self == other {
+            self
+        } else {
+            FixupKind::CaseAndUnicode
+        }
+    }
+}
+
+/// A request path that was served from a file with a different name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fixup {
+    pub path: String,
+    /// The file served, relative to the site root
+    pub file: String,
+    pub kind: FixupKind,
+    pub count: u64,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+/// The fixups of one server, most requested first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixupSnapshot {
+    pub port: u16,
+    pub root: PathBuf,
+    pub fixups: Vec<Fixup>,
+}
+
+/// Collects the paths that only resolved after fixing case or normalization
+///
+/// These are links that work on macOS but break elsewhere, so the files (or
+/// links) are worth renaming. Shown at `/__hopen/fixups` and saved for
+/// `hopen report fixups`.
+pub type FixupReport = ReportStore<FixupSnapshot>;
+
+impl ReportSnapshot for FixupSnapshot {
+    type Entry = Fixup;
+    const NAME: &'static str = "fixups";
+    const TITLE: &'static str = "Path fixups";
+
+    fn new(port: u16, root: PathBuf, fixups: Vec<Fixup>) -> Self {
+        FixupSnapshot { port, root, fixups }
+    }
+
+    fn count(fixup: &Fixup) -> u64 {
+        fixup.count
+    }
+
+    fn render_html(&self) -> String {
+        render_html(self)
+    }
+}
+
+/// Count a request for `path` that was served from `file`
+pub fn record(report: &Arc<FixupReport>, path: &str, file: &str, kind: FixupKind) {
+    let now = access_log::timestamp();
+    report.record(
+        path,
+        || Fixup {
+            path: path.to_string(),
+            file: file.to_string(),
+            kind,
+            count: 0,
+            first_seen: now.clone(),
+            last_seen: now.clone(),
+        },
+        |fixup| {
+            fixup.count += 1;
+            fixup.last_seen = now.clone();
+        },
+    );
+}
+
+/// Read the saved report of the server on a port
+pub fn load(port: u16) -> Result<FixupSnapshot> {
+    reports::load(port)
+}
+
+fn render_html(snapshot: &FixupSnapshot) -> String {
+    if snapshot.fixups.is_empty() {
+        return "<p>No path fixups so far. They are made with \
+                <code>--ignore-case</code> and <code>--normalize-unicode</code>.</p>"
+            .to_string();
+    }
+    let mut html = format!(
+        "<p>{} paths were served from a file with a different name under <code>{}</code>. \
+         <a href=\"?format=json\">JSON</a></p>\n\
+         <table>\n<tr><th>Requests</th><th>Path</th><th>File</th><th>Fixup</th></tr>\n",
+        snapshot.fixups.len(),
+        pages::escape(&snapshot.root.display().to_string())
+    );
+    for fixup in &snapshot.fixups {
+        html.push_str(&format!(
+            "<tr><td class=\"count\">{}</td><td><code>{}</code></td>\
+             <td><code>{}</code></td><td>{}</td></tr>\n",
+            fixup.count,
+            pages::escape(&fixup.path),
+            pages::escape(&fixup.file),
+            fixup.kind.label()
+        ));
+    }
+    html.push_str("</table>");
+    html
+}
+
+/// Print a report for `hopen report fixups`
+pub fn print(snapshot: &FixupSnapshot) {
+    if snapshot.fixups.is_empty() {
+        println!(
+            "{}",
+            format!("No path fixups on port {}", snapshot.port).green()
+        );
+        return;
+    }
+    println!(
+        "{} {}",
+        format!("Path fixups on port {}:", snapshot.port).bold(),
+        snapshot.root.display().to_string().magenta()
+    );
+    for fixup in &snapshot.fixups {
+        println!(
+            "{:>6}  {} {} {} {}",
+            fixup.count.to_string().yellow(),
+            fixup.path.cyan(),
+            "→".dimmed(),
+            fixup.file,
+            format!("({})", fixup.kind.label()).dimmed()
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use warp::http::{header, Response, StatusCode};
+use warp::host::Authority;
+use warp::hyper::Body;
+use warp::{Filter, Rejection};
+
+/// Host names a request may be addressed to
+///
+/// Checking the `Host` header protects against DNS rebinding, where a
+/// malicious page points its own domain at 127.0.0.1 to read our server.
+#[derive(Debug, Clone)]
+pub struct HostPolicy {
+    /// Lowercase host patterns without port; `*.example.test` matches
+    /// subdomains and `*` matches anything
+    allowed: Vec<String>,
+}
+
+impl HostPolicy {
+    pub fn new(allowed: Vec<String>) -> Self {
+        Self {
+            allowed: allowed.iter().map(|h| normalize(h)).collect(),
+        }
+    }
+
+    fn allows(&self, host: &str) -> bool {
+        let host = normalize(host);
+        self.allowed.iter().any(|pattern| {
+            pattern == "*"
+                || *pattern == host
+                || pattern
+                    .strip_prefix("*.")
+                    .is_some_and(|domain| host.ends_with(&format!(".{}", domain)))
+        })
+    }
+
+    fn check(&self, authority: Option<Authority>) -> Result<(), Rejection> {
+        match authority {
+            Some(authority) if self.allows(authority.host()) => Ok(()),
+            Some(authority) => Err(warp::reject::custom(MisdirectedHost {
+                host: authority.host().to_string(),
+            })),
+            None => Err(warp::reject::custom(MisdirectedHost {
+                host: String::new(),
+            })),
+        }
+    }
+}
+
+/// Lowercase a host and drop a trailing dot (`localhost.` is `localhost`)
+fn normalize(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Filter that lets a request through only if its Host is allowed
+pub fn filter(policy: Arc<HostPolicy>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::host::optional()
+        .and_then(move |authority: Option<Authority>| {
+            let policy = policy.clone();
+            async move { policy.check(authority) }
+        })
+        .untuple_one()
+}
+
+/// Rejection: the request's Host is missing or not one we serve
+#[derive(Debug)]
+pub struct MisdirectedHost {
+    host: String,
+}
+
+impl warp::reject::Reject for MisdirectedHost {}
+
+impl MisdirectedHost {
+    /// 421 for an unknown host, 403 when no host was given at all
+    pub fn response(&self) -> Response<Body> {
+        let (status, body) = if self.host.is_empty() {
+            (StatusCode::FORBIDDEN, "403 Forbidden: missing Host header\n".to_string())
+        } else {
+            (
+                StatusCode::MISDIRECTED_REQUEST,
+                format!(
+                    "421 Misdirected Request: host '{}' is not allowed (see --allow-host)\n",
+                    self.host
+                ),
+            )
+        };
+        Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(Body::from(body))
+            .unwrap_or_default()
+    }
+}
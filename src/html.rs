@@ -0,0 +1,305 @@
+use std::collections::HashSet;
+
+/// A URL referenced by a page or stylesheet, as written (entities decoded)
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub url: String,
+    /// Byte offset in the document, for line numbers
+    pub offset: usize,
+    /// Whether it is a link to follow (`<a href>`, `<area href>`) rather
+    /// than a resource of the page
+    pub is_link: bool,
+}
+
+/// Elements that don't separate words (`<b>bold</b>er` is one word)
+const INLINE: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "cite", "code", "data", "dfn", "em", "i", "kbd", "mark",
+    "q", "s", "samp", "small", "span", "strong", "sub", "sup", "time", "u", "var", "wbr",
+];
+
+/// What `scan` finds in an HTML document
+#[derive(Debug, Default)]
+pub struct Document {
+    /// `href`, `src` and `srcset` attributes, and `url()` in styles
+    pub references: Vec<Reference>,
+    /// `id` attributes and `<a name>`, the fragments links may point at
+    pub anchors: HashSet<String>,
+    /// The `<base href>`, if any
+    pub base: Option<String>,
+    /// The `<title>`, whitespace collapsed
+    pub title: Option<String>,
+    /// The text of the page (without title, scripts and styles),
+    /// whitespace collapsed
+    pub text: String,
+}
+
+/// Scan an HTML document for references, anchors and text
+///
+/// This is a tolerant tokenizer rather than a parser: it reads tags and
+/// their attributes, skips comments and scripts, and looks for `url()` in
+/// `<style>` elements and `style` attributes.
+pub fn scan(html: &str) -> Document {
+    let mut document = Document::default();
+    let mut text = String::new();
+    let mut i = 0;
+    while let Some(found) = html[i..].find('<') {
+        let start = i + found;
+        text.push_str(&html[i..start]);
+        let rest = &html[start..];
+        if rest.starts_with("<!--") {
+            i = rest.find("-->").map_or(html.len(), |end| start + end + 3);
+            continue;
+        }
+        // Closing tags, doctype and processing instructions
+        if rest[1..].starts_with(['/', '!', '?']) {
+            let name = rest[2..]
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .next()
+                .unwrap_or("");
+            if !INLINE.iter().any(|inline| name.eq_ignore_ascii_case(inline)) {
+                text.push(' ');
+            }
+            i = (tag_end(html, start + 1) + 1).min(html.len());
+            continue;
+        }
+        let Some(name_len) = rest[1..].find(|c: char| !c.is_ascii_alphanumeric()) else {
+            break;
+        };
+        if name_len == 0 || !rest.as_bytes()[1].is_ascii_alphabetic() {
+            // A literal `<`
+            text.push('<');
+            i = start + 1;
+            continue;
+        }
+        let name = rest[1..=name_len].to_ascii_lowercase();
+        if !INLINE.contains(&name.as_str()) {
+            text.push(' ');
+        }
+        let end = tag_end(html, start + 1 + name_len);
+        for (attribute, value, offset) in attributes(html, start + 1 + name_len, end) {
+            match attribute.as_str() {
+                "href" | "src" => {
+                    if name == "base" && attribute == "href" {
+                        document.base.get_or_insert(value);
+                    } else {
+                        document.references.push(Reference {
+                            url: value,
+                            offset,
+                            is_link: attribute == "href" && (name == "a" || name == "area"),
+                        });
+                    }
+                }
+                "srcset" => document.references.extend(srcset(&value, offset)),
+                "style" => document.references.extend(css_references(&value, offset)),
+                "id" => {
+                    document.anchors.insert(value);
+                }
+                "name" if name == "a" => {
+                    document.anchors.insert(value);
+                }
+                _ => {}
+            }
+        }
+        i = (end + 1).min(html.len());
+        // Scripts aren't HTML, styles are CSS and titles aren't page text
+        if name == "script" || name == "style" || name == "title" {
+            let close = find_ignore_case(&html[i..], &format!("</{}", name))
+                .map_or(html.len(), |at| i + at);
+            if name == "style" {
+                document.references.extend(css_references(&html[i..close], i));
+            } else if name == "title" && document.title.is_none() {
+                document.title = Some(collapse_whitespace(&decode_entities(&html[i..close])));
+            }
+            i = close;
+        }
+    }
+    text.push_str(&html[i..]);
+    document.text = collapse_whitespace(&decode_entities(&text));
+    document
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// References in CSS: `url(...)` and `@import "..."`
+pub fn css_references(css: &str, offset: usize) -> Vec<Reference> {
+    let mut references = Vec::new();
+    let mut i = 0;
+    while let Some(found) = find_ignore_case(&css[i..], "url(") {
+        let start = i + found + 4;
+        let end = css[start..].find(')').map_or(css.len(), |at| start + at);
+        let url = css[start..end].trim().trim_matches(|c| c == '"' || c == '\'');
+        references.push(Reference {
+            url: url.to_string(),
+            offset: offset + start,
+            is_link: false,
+        });
+        i = end;
+    }
+    let mut i = 0;
+    while let Some(found) = find_ignore_case(&css[i..], "@import") {
+        let start = i + found + 7;
+        let rest = css[start..].trim_start();
+        let quoted = rest.chars().next().filter(|&c| c == '"' || c == '\'');
+        if let Some(quote) = quoted {
+            if let Some(len) = rest[1..].find(quote) {
+                references.push(Reference {
+                    url: rest[1..=len].to_string(),
+                    offset: offset + css.len() - rest.len(),
+                    is_link: false,
+                });
+            }
+        }
+        i = start;
+    }
+    references
+}
+
+/// Line number (from 1) of a byte offset
+pub fn line_of(text: &str, offset: usize) -> usize {
+    text.as_bytes()[..offset.min(text.len())]
+        .iter()
+        .filter(|&&b| b == b'\n')
+        .count()
+        + 1
+}
+
+/// Named character references: those of markup, and those common in older
+/// pages
+const NAMED_ENTITIES: &[(&str, char)] = &[
+    ("amp", '&'), ("lt", '<'), ("gt", '>'), ("quot", '"'), ("apos", '\''), ("nbsp", '\u{a0}'),
+    ("copy", '©'), ("reg", '®'), ("trade", '™'), ("euro", '€'), ("pound", '£'),
+    ("hellip", '…'), ("mdash", '—'), ("ndash", '–'), ("lsquo", '‘'), ("rsquo", '’'),
+    ("ldquo", '“'), ("rdquo", '”'), ("laquo", '«'), ("raquo", '»'), ("middot", '·'),
+    ("aacute", 'á'), ("agrave", 'à'), ("acirc", 'â'), ("auml", 'ä'), ("atilde", 'ã'),
+    ("aring", 'å'), ("aelig", 'æ'), ("ccedil", 'ç'), ("eacute", 'é'), ("egrave", 'è'),
+    ("ecirc", 'ê'), ("euml", 'ë'), ("iacute", 'í'), ("igrave", 'ì'), ("icirc", 'î'),
+    ("iuml", 'ï'), ("ntilde", 'ñ'), ("oacute", 'ó'), ("ograve", 'ò'), ("ocirc", 'ô'),
+    ("ouml", 'ö'), ("otilde", 'õ'), ("oslash", 'ø'), ("uacute", 'ú'), ("ugrave", 'ù'),
+    ("ucirc", 'û'), ("uuml", 'ü'), ("yacute", 'ý'), ("szlig", 'ß'), ("Aacute", 'Á'),
+    ("Agrave", 'À'), ("Auml", 'Ä'), ("Ccedil", 'Ç'), ("Eacute", 'É'), ("Egrave", 'È'),
+    ("Ntilde", 'Ñ'), ("Ouml", 'Ö'), ("Uuml", 'Ü'),
+];
+
+/// Decode the character references that show up in URLs, ids and text
+pub fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        decoded.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let entity = rest[1..].find(';').map(|end| &rest[1..=end]);
+        let character = entity.and_then(|entity| match entity.strip_prefix('#') {
+            Some(number) => {
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+            None => NAMED_ENTITIES
+                .iter()
+                .find(|(name, _)| *name == entity)
+                .map(|(_, character)| *character),
+        });
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Offset of the `>` closing a tag, skipping quoted attribute values
+fn tag_end(html: &str, from: usize) -> usize {
+    let bytes = html.as_bytes();
+    let mut quote = None;
+    for (i, &b) in bytes.iter().enumerate().skip(from) {
+        match quote {
+            Some(q) if b == q => quote = None,
+            Some(_) => {}
+            None if b == b'"' || b == b'\'' => quote = Some(b),
+            None if b == b'>' => return i,
+            None => {}
+        }
+    }
+    html.len()
+}
+
+/// Attributes of a tag between `from` and `end`: lowercased name, decoded
+/// value and the value's offset
+fn attributes(html: &str, from: usize, end: usize) -> Vec<(String, String, usize)> {
+    let tag = &html[..end];
+    let bytes = tag.as_bytes();
+    let mut attributes = Vec::new();
+    let mut i = from;
+    while i < end {
+        if bytes[i].is_ascii_whitespace() || bytes[i] == b'/' {
+            i += 1;
+            continue;
+        }
+        let name_start = i;
+        while i < end && !bytes[i].is_ascii_whitespace() && !matches!(bytes[i], b'=' | b'/') {
+            i += 1;
+        }
+        let name = tag[name_start..i].to_ascii_lowercase();
+        while i < end && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= end || bytes[i] != b'=' {
+            continue;
+        }
+        i += 1;
+        while i < end && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let (value_start, value_end) = if i < end && matches!(bytes[i], b'"' | b'\'') {
+            let quote = bytes[i];
+            let close = tag[i + 1..].find(quote as char).map_or(end, |at| i + 1 + at);
+            (i + 1, close)
+        } else {
+            let close = tag[i..]
+                .find(|c: char| c.is_ascii_whitespace())
+                .map_or(end, |at| i + at);
+            (i, close)
+        };
+        attributes.push((name, decode_entities(&tag[value_start..value_end]), value_start));
+        i = value_end + 1;
+    }
+    attributes
+}
+
+/// The URLs of a `srcset` (each candidate is a URL and a size descriptor)
+fn srcset(value: &str, offset: usize) -> Vec<Reference> {
+    // Commas can't separate candidates inside a data: URL
+    if value.trim_start().starts_with("data:") {
+        return Vec::new();
+    }
+    value
+        .split(',')
+        .filter_map(|candidate| candidate.split_whitespace().next())
+        .map(|url| Reference {
+            url: url.to_string(),
+            offset,
+            is_link: false,
+        })
+        .collect()
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
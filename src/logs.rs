@@ -0,0 +1,190 @@
+use anyhow::{bail, Context, Result};
+use colored::*;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::registry::{self, ServerRecord};
+
+/// Rotated copies kept next to a log (`server-8000.log.1` ... `.3`)
+const ROTATED_LOGS: u32 = 3;
+
+/// Logs of servers that are no longer running are removed after this long
+const PRUNE_AFTER: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Arguments of `hopen logs`
+#[derive(clap::Args, Debug)]
+pub struct LogsArgs {
+    /// Server to show: a port, or a directory inside its site root.
+    /// Defaults to the server for the current directory.
+    #[arg(value_name = "PORT|ROOT")]
+    pub target: Option<String>,
+
+    /// Keep printing new lines as they are logged (until the server stops)
+    #[arg(short = 'f', long = "follow")]
+    pub follow: bool,
+}
+
+/// Find the registered server a `hopen logs` target refers to
+pub fn find_server(target: Option<&str>, current_dir: &Path) -> Result<ServerRecord> {
+    if let Some(port) = target.and_then(|t| t.parse::<u16>().ok()) {
+        return registry::find_by_port(port)
+            .with_context(|| format!("No hopen server is running on port {}", port));
+    }
+    if let Some(root) = target {
+        return registry::find_by_path(Path::new(root))
+            .with_context(|| format!("No hopen server is serving {}", root));
+    }
+    if let Some(record) = registry::find_by_path(current_dir) {
+        return Ok(record);
+    }
+
+    // Not inside any served root: fine as long as there is only one server
+    let mut servers = registry::running_servers();
+    match servers.len() {
+        0 => bail!("No hopen server is running"),
+        1 => Ok(servers.remove(0)),
+        _ => {
+            let list: Vec<String> = servers
+                .iter()
+                .map(|s| format!("  {}  {}", s.port, s.root.display()))
+                .collect();
+            bail!(
+                "Several hopen servers are running; pick one by port or root:\n{}",
+                list.join("\n")
+            )
+        }
+    }
+}
+
+/// Print a server's log, then keep following it with `follow`
+pub fn show(record: &ServerRecord, follow: bool) -> Result<()> {
+    let Some(ref log) = record.log else {
+        bail!(
+            "The server on port {} runs in the foreground; its requests are logged in its terminal",
+            record.port
+        );
+    };
+    let mut file =
+        File::open(log).with_context(|| format!("Failed to open {}", log.display()))?;
+    let mut stdout = io::stdout();
+    let mut position = io::copy(&mut file, &mut stdout)?;
+    if !follow {
+        return Ok(());
+    }
+
+    loop {
+        std::thread::sleep(Duration::from_millis(250));
+        let len = file.metadata()?.len();
+        if len < position {
+            // Truncated: start again from the beginning
+            file.seek(SeekFrom::Start(0))?;
+            position = 0;
+        }
+        let mut chunk = Vec::new();
+        position += file.read_to_end(&mut chunk)? as u64;
+        stdout.write_all(&chunk)?;
+        stdout.flush()?;
+
+        if chunk.is_empty() && registry::find_by_port(record.port).is_none() {
+            status!("{}", format!("Server on port {} stopped", record.port).yellow());
+            return Ok(());
+        }
+    }
+}
+
+/// Open a background server's log for appending, rotating it first if needed
+///
+/// The returned handle is meant to be shared (via `try_clone`) by the
+/// server's stdout and stderr, so their writes interleave instead of
+/// overwriting each other.
+pub fn open_log_file(path: &Path, max_bytes: u64) -> Result<File> {
+    rotate_if_needed(path, max_bytes)?;
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    // Only we should read what was requested from our servers
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+/// Rotate a log once it grows past `max_bytes`, returning its size after
+///
+/// The log is copied to `.1` (shifting older copies up) and then truncated
+/// in place, so a server writing to it keeps a valid handle; since it
+/// appends, its next write lands at the start of the emptied file.
+pub fn rotate_if_needed(path: &Path, max_bytes: u64) -> Result<u64> {
+    let len = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return Ok(0),
+    };
+    if len <= max_bytes {
+        return Ok(len);
+    }
+    for n in (1..ROTATED_LOGS).rev() {
+        let from = rotated_path(path, n);
+        if from.exists() {
+            fs::rename(&from, rotated_path(path, n + 1))?;
+        }
+    }
+    fs::copy(path, rotated_path(path, 1))?;
+    OpenOptions::new().write(true).open(path)?.set_len(0)?;
+    Ok(0)
+}
+
+fn rotated_path(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Remove old logs of servers that are no longer running
+///
+/// Logs are kept for a while after their server stops so a crash can still
+/// be looked into. Also cleans up the per-start `/tmp/hopen-server-*.log`
+/// files written by earlier versions.
+pub fn prune_logs() {
+    let running: Vec<u16> = registry::running_servers()
+        .iter()
+        .map(|record| record.port)
+        .collect();
+    let is_old = |path: &Path| {
+        fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age > PRUNE_AFTER)
+    };
+
+    if let Ok(entries) = registry::logs_dir().and_then(|dir| Ok(fs::read_dir(dir)?)) {
+        for path in entries.flatten().map(|entry| entry.path()) {
+            let port = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("server-"))
+                .and_then(|name| name.split('.').next())
+                .and_then(|port| port.parse::<u16>().ok());
+            if port.is_some_and(|port| !running.contains(&port)) && is_old(&path) {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+
+    if let Ok(entries) = fs::read_dir(std::env::temp_dir()) {
+        for path in entries.flatten().map(|entry| entry.path()) {
+            let legacy = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("hopen-server-") && name.ends_with(".log"));
+            if legacy && is_old(&path) {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+}
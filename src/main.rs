@@ -1,22 +1,80 @@
 use anyhow::{bail, Context, Result};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use colored::*;
 use inquire::Select;
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+use serde::Serialize;
 use std::env;
 use std::fs;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
+use std::io::IsTerminal;
+use std::net::TcpListener;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
 use sysinfo::{Pid, System};
 
 const DEFAULT_PORT: u16 = 8000;
 const MAX_PORT: u16 = 8100;
 
+/// How long a --browser command is watched for failing
+const BROWSER_COMMAND_GRACE: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// When set, human-readable status output goes to stderr so that stdout only
+/// carries machine-readable output (e.g. the URL with --print-url)
+static STATUS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// When set (--json), each command emits a single JSON result on stdout
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Print a human-readable status line (stdout, or stderr in machine-output modes)
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::STATUS_TO_STDERR.load(std::sync::atomic::Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod access_log;
+mod archive;
+mod auth;
+mod check;
+mod domains;
+mod fallback;
+mod files;
+mod fixups;
+mod hosts;
+mod html;
+mod logs;
+mod mhtml;
+mod mirror;
+mod missing;
+mod pages;
+mod policy;
+mod registry;
+mod reports;
+mod rewrite;
+mod search;
+mod server;
+mod sitemap;
+mod tls;
+mod warc;
+
+use policy::ServePolicy;
+use registry::ServerRecord;
+use server::{run_server, ServeArgs};
+
 /// hopen - Start a local HTTP server for HTML files
 ///
-/// Usage: hopen [-e] [-f] [-m] [-p] [-r site_home] [filename]
+/// Usage: hopen [-e] [-f] [-m] [-p] [-n] [-b browser] [-r site_home] [filename]
+///        hopen logs [port|root] [-f]
+///        hopen report missing [port|root]
+///        hopen report fixups [port|root]
+///        hopen check [root]
+///        hopen sitemap [root] [-o FILE]
 ///
 /// When site_home is set (via -r or HOPEN_SITE_HOME), the server runs from that directory.
 /// The URL path is calculated as: (relative path from site_home to PWD) + filename
@@ -27,12 +85,17 @@ const MAX_PORT: u16 = 8100;
 ///   filename  = post.html
 ///   Server runs from: /Users/me/www.example.com
 ///   URL: http://localhost:8000/blog/post.html
+///
+/// When site_home is an archive (site.zip, site.tar.gz, capture.warc, page.mhtml), the site is
+/// served from it and the URL path is just the filename. `hopen <archive>` is
+/// short for `hopen -r <archive>`.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 #[command(after_help = "Environment variables:
   HOPEN_SITE_HOME    Set default site root directory (same as -r/--root)
                      Add to ~/.zshrc or ~/.bashrc:
-                     export HOPEN_SITE_HOME=/path/to/your/site/root")]
+                     export HOPEN_SITE_HOME=/path/to/your/site/root
+  HOPEN_BROWSER      Set default browser (same as -b/--browser)")]
 struct Args {
     /// Optional HTML file to open in the browser
     filename: Option<String>,
@@ -40,6 +103,8 @@ struct Args {
     /// Specify the site root directory where the server will run.
     /// Useful for static site mirrors (e.g., from SiteSucker) where you want
     /// to browse files in subdirectories while maintaining correct relative paths.
+    /// A .zip, .tar, .tar.gz or .tgz archive, a .warc(.gz) web archive or an
+    /// .mhtml page is served without unpacking it.
     #[arg(short = 'r', long = "root")]
     site_home: Option<String>,
 
@@ -60,6 +125,38 @@ struct Args {
     #[arg(short = 'p', long = "prompt")]
     prompt: bool,
 
+    /// Answer a menu non-interactively (implies -m): open the browser, quit the
+    /// running server, quit and restart here, cancel, or show the network QR code
+    #[arg(short = 'a', long = "action", value_enum)]
+    action: Option<MenuAction>,
+
+    /// Answer "yes" to the -p "Open in browser now?" prompt
+    #[arg(short = 'y', long = "yes")]
+    yes: bool,
+
+    /// Browser to open the URL with: an application name (e.g. firefox) or a
+    /// command line where {url} is replaced by the URL. Defaults to the system browser.
+    #[arg(short = 'b', long = "browser", value_name = "NAME|COMMAND")]
+    browser: Option<String>,
+
+    /// Do not open a browser; just start (or reuse) the server
+    #[arg(short = 'n', long = "no-open")]
+    no_open: bool,
+
+    /// Print only the final URL on stdout (status messages go to stderr)
+    #[arg(long = "print-url", conflicts_with = "json")]
+    print_url: bool,
+
+    /// Emit the result as JSON on stdout (status messages go to stderr)
+    #[arg(long = "json", global = true)]
+    json: bool,
+
+    #[command(flatten)]
+    serve: ServeArgs,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// Internal flag: run as a background server (used when spawning ourselves)
     #[arg(long = "internal-serve", hide = true)]
     internal_serve: bool,
@@ -71,6 +168,60 @@ struct Args {
     /// Internal flag: directory to serve (used with --internal-serve)
     #[arg(long = "internal-dir", hide = true)]
     internal_dir: Option<String>,
+
+    /// Internal flag: log file behind stdout/stderr (used with --internal-serve)
+    #[arg(long = "internal-log", hide = true)]
+    internal_log: Option<PathBuf>,
+}
+
+/// Subcommands (without one, hopen starts or reuses a server)
+#[derive(clap::Subcommand, Debug)]
+enum Commands {
+    /// Print the log of a background server
+    Logs(logs::LogsArgs),
+
+    /// Print what a server has seen
+    #[command(subcommand)]
+    Report(ReportKind),
+
+    /// Check the site's links offline: broken links, missing anchors and
+    /// references leaving the root. Exits with 1 when any are found.
+    Check(check::CheckArgs),
+
+    /// List the site's pages as sitemap.xml, or as a JSON or CSV inventory
+    /// with each page's title, size, last modification and number of links
+    Sitemap(sitemap::SitemapArgs),
+}
+
+/// Reports available with `hopen report`
+#[derive(clap::Subcommand, Debug)]
+enum ReportKind {
+    /// Paths that were not found (404), with the pages that referred to them
+    Missing {
+        /// Server to report on: a port, or a directory inside its site root.
+        /// Defaults to the server for the current directory.
+        #[arg(value_name = "PORT|ROOT")]
+        target: Option<String>,
+    },
+
+    /// Paths that were served only after fixing their letter case or Unicode
+    /// normalization (--ignore-case, --normalize-unicode)
+    Fixups {
+        /// Server to report on: a port, or a directory inside its site root.
+        /// Defaults to the server for the current directory.
+        #[arg(value_name = "PORT|ROOT")]
+        target: Option<String>,
+    },
+}
+
+/// Preselected menu answer (--action)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MenuAction {
+    Open,
+    Quit,
+    Restart,
+    Cancel,
+    Qr,
 }
 
 /// Menu choices when a server is already running
@@ -80,6 +231,19 @@ enum ExistingServerMenu {
     QuitServer,
     QuitAndRestart,
     Cancel,
+    ShowQrCode,
+}
+
+impl From<MenuAction> for ExistingServerMenu {
+    fn from(action: MenuAction) -> Self {
+        match action {
+            MenuAction::Open => ExistingServerMenu::OpenBrowser,
+            MenuAction::Quit => ExistingServerMenu::QuitServer,
+            MenuAction::Restart => ExistingServerMenu::QuitAndRestart,
+            MenuAction::Cancel => ExistingServerMenu::Cancel,
+            MenuAction::Qr => ExistingServerMenu::ShowQrCode,
+        }
+    }
 }
 
 impl std::fmt::Display for ExistingServerMenu {
@@ -89,6 +253,7 @@ impl std::fmt::Display for ExistingServerMenu {
             ExistingServerMenu::QuitServer => write!(f, "2) Quit the existing server"),
             ExistingServerMenu::QuitAndRestart => write!(f, "3) Quit and restart here"),
             ExistingServerMenu::Cancel => write!(f, "4) Cancel and leave everything unchanged"),
+            ExistingServerMenu::ShowQrCode => write!(f, "5) Show QR code of the network URL"),
         }
     }
 }
@@ -111,10 +276,77 @@ impl std::fmt::Display for StartupMenu {
     }
 }
 
+/// Result of a command, printed on stdout with --json
+#[derive(Debug, Default, Serialize)]
+struct Report {
+    /// What hopen did: started, reused, opened, stopped, not_running, cancelled, qr,
+    /// logs or error
+    action: &'static str,
+    pid: Option<u32>,
+    port: Option<u16>,
+    root: Option<String>,
+    url: Option<String>,
+    log: Option<String>,
+    foreground: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Report {
+    fn new(action: &'static str) -> Self {
+        Self {
+            action,
+            ..Default::default()
+        }
+    }
+
+    /// Print the report as JSON (only with --json)
+    fn emit(&self) {
+        if JSON_OUTPUT.load(Ordering::Relaxed) {
+            match serde_json::to_string(self) {
+                Ok(json) => println!("{}", json),
+                Err(e) => eprintln!("Failed to serialize result: {}", e),
+            }
+        }
+    }
+
+    /// Report a failure and exit with a non-zero status
+    fn fail(message: &str) -> ! {
+        Report {
+            error: Some(message.to_string()),
+            ..Report::new("error")
+        }
+        .emit();
+        std::process::exit(1);
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    args.serve.access_token = env::var(auth::ACCESS_TOKEN_ENV).ok();
+    if args.internal_serve && args.serve.auth.is_none() {
+        args.serve.auth = env::var(auth::CREDENTIALS_ENV).ok();
+    }
+    args.serve.ensure_access_token();
+
+    if args.json {
+        JSON_OUTPUT.store(true, Ordering::Relaxed);
+        STATUS_TO_STDERR.store(true, Ordering::Relaxed);
+    }
 
+    let result = run(args).await;
+    if let Err(ref e) = result {
+        Report {
+            error: Some(e.to_string()),
+            ..Report::new("error")
+        }
+        .emit();
+    }
+    result
+}
+
+async fn run(mut args: Args) -> Result<()> {
     // =========================================================================
     // Internal Server Mode (spawned by ourselves for background operation)
     // =========================================================================
@@ -124,7 +356,102 @@ async fn main() -> Result<()> {
             .map(PathBuf::from)
             .unwrap_or_else(|| env::current_dir().unwrap_or_default());
 
-        run_server(&dir, port).await?;
+        run_server(&dir, port, &args.serve, args.internal_log.as_deref()).await?;
+        return Ok(());
+    }
+
+    // =========================================================================
+    // Subcommands
+    // =========================================================================
+    if let Some(Commands::Logs(ref logs_args)) = args.command {
+        let current_dir = env::current_dir().context("Failed to get current directory")?;
+        let record = logs::find_server(logs_args.target.as_deref(), &current_dir)?;
+        if JSON_OUTPUT.load(Ordering::Relaxed) {
+            // The log itself would not be JSON: report where it is instead
+            Report {
+                pid: Some(record.pid),
+                port: Some(record.port),
+                root: Some(record.root.display().to_string()),
+                url: Some(record.url.clone()),
+                log: record.log.as_ref().map(|l| l.display().to_string()),
+                foreground: record.foreground,
+                ..Report::new("logs")
+            }
+            .emit();
+            return Ok(());
+        }
+        return logs::show(&record, logs_args.follow);
+    }
+    if let Some(Commands::Check(ref check_args)) = args.command {
+        let root = command_root(check_args.root.as_ref(), args.site_home.as_deref())?;
+        let report = check::check(&root, &args.serve)?;
+        if JSON_OUTPUT.load(Ordering::Relaxed) {
+            println!("{}", serde_json::to_string(&report)?);
+        } else {
+            check::print(&report);
+        }
+        if !report.problems.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some(Commands::Sitemap(ref sitemap_args)) = args.command {
+        let root = command_root(sitemap_args.root.as_ref(), args.site_home.as_deref())?;
+        let policy = ServePolicy::new(&root, &args.serve.allow_dotfile, args.serve.follow_symlinks);
+        let inventory = sitemap::inventory(&root, &policy);
+        let base_url = sitemap_args
+            .base_url
+            .clone()
+            .or_else(|| args.serve.rewrite_origin.first().cloned())
+            .unwrap_or_else(|| format!("http://localhost:{}", DEFAULT_PORT));
+        if sitemap_args.output.is_empty() {
+            let format = if JSON_OUTPUT.load(Ordering::Relaxed) {
+                sitemap::Format::Json
+            } else {
+                sitemap_args.format
+            };
+            print!("{}", inventory.render(format, &base_url));
+            return Ok(());
+        }
+        sitemap::write(&inventory, &sitemap_args.output, &base_url)?;
+        for output in &sitemap_args.output {
+            status!(
+                "{} {}",
+                format!("Listed {} pages in", inventory.pages.len()).green(),
+                output.display()
+            );
+        }
+        return Ok(());
+    }
+    if let Some(Commands::Report(ref kind)) = args.command {
+        let (ReportKind::Missing { target } | ReportKind::Fixups { target }) = kind;
+        // A port works even after its server stopped, since the report is saved
+        let port = match target.as_deref().and_then(|t| t.parse::<u16>().ok()) {
+            Some(port) => port,
+            None => {
+                let current_dir = env::current_dir().context("Failed to get current directory")?;
+                logs::find_server(target.as_deref(), &current_dir)?.port
+            }
+        };
+        let json = JSON_OUTPUT.load(Ordering::Relaxed);
+        match kind {
+            ReportKind::Missing { .. } => {
+                let snapshot = missing::load(port)?;
+                if json {
+                    println!("{}", serde_json::to_string(&snapshot)?);
+                } else {
+                    missing::print(&snapshot);
+                }
+            }
+            ReportKind::Fixups { .. } => {
+                let snapshot = fixups::load(port)?;
+                if json {
+                    println!("{}", serde_json::to_string(&snapshot)?);
+                } else {
+                    fixups::print(&snapshot);
+                }
+            }
+        }
         return Ok(());
     }
 
@@ -133,6 +460,13 @@ async fn main() -> Result<()> {
     // =========================================================================
     let current_dir = env::current_dir().context("Failed to get current directory")?;
 
+    // `hopen site.zip` or `hopen capture.warc` serves the archive, like -r
+    if args.site_home.is_none() {
+        if let Some(file) = args.filename.take_if(|f| is_site_archive(Path::new(f))) {
+            args.site_home = Some(file);
+        }
+    }
+
     // Determine site_home: -r flag -> HOPEN_SITE_HOME env var -> None
     let site_home: Option<PathBuf> = args
         .site_home
@@ -143,6 +477,31 @@ async fn main() -> Result<()> {
             path.canonicalize().unwrap_or(path)
         });
 
+    // A site archive (-r site.zip) is served as is, from any directory
+    let serving_archive = site_home.as_deref().is_some_and(is_site_archive);
+    if serving_archive && args.serve.domains.is_some() {
+        bail!("--domains can't be used with an archive");
+    }
+
+    // Determine browser: -b flag -> HOPEN_BROWSER env var -> system default
+    let browser: Option<String> = args
+        .browser
+        .clone()
+        .or_else(|| env::var("HOPEN_BROWSER").ok())
+        .filter(|b| !b.trim().is_empty());
+
+    if args.print_url {
+        STATUS_TO_STDERR.store(true, Ordering::Relaxed);
+    }
+
+    let launch = BrowserLaunch {
+        browser,
+        prompt: args.prompt,
+        assume_yes: args.yes,
+        no_open: args.no_open,
+        print_url: args.print_url,
+    };
+
     // Validate: filename requires site_home
     if args.filename.is_some() && site_home.is_none() {
         bail!(
@@ -155,7 +514,15 @@ async fn main() -> Result<()> {
     // =========================================================================
     // When site_home is set, we calculate the URL path as:
     // (relative path from site_home to PWD) + filename
-    let (server_dir, url_path) = if let Some(ref sh) = site_home {
+    let (server_dir, url_path) = if let Some(sh) = site_home.as_ref().filter(|_| serving_archive) {
+        // The URL path is just the filename (if any) inside the archive
+        let url = args
+            .filename
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        (sh.clone(), url)
+    } else if let Some(ref sh) = site_home {
         // Validate: PWD must be under site_home
         if !current_dir.starts_with(sh) {
             eprintln!("{}", "Error: Current directory is not under site_home".red());
@@ -165,7 +532,7 @@ async fn main() -> Result<()> {
                 "Current directory:".cyan(),
                 current_dir.display().to_string().magenta()
             );
-            std::process::exit(1);
+            Report::fail("Current directory is not under site_home");
         }
 
         // Calculate relative path from site_home to PWD
@@ -194,7 +561,7 @@ async fn main() -> Result<()> {
     // =========================================================================
     // 3. Check for HTML Files
     // =========================================================================
-    if !has_html_files(&current_dir) {
+    if !serving_archive && !has_site_files(&current_dir, &args.serve) {
         eprintln!(
             "{}",
             "✗ No HTML files found in current directory".red().bold()
@@ -208,7 +575,7 @@ async fn main() -> Result<()> {
             "Current directory:".cyan(),
             current_dir.display().to_string().magenta()
         );
-        std::process::exit(1);
+        Report::fail("No HTML files found in current directory");
     }
 
     // =========================================================================
@@ -224,13 +591,22 @@ async fn main() -> Result<()> {
     // =========================================================================
     if args.exit {
         if let Some((pid, existing_port)) = existing_server {
+            let root = get_server_root(pid);
             kill_process(pid)?;
-            println!(
+            status!(
                 "{}",
                 format!("✓ Server stopped (PID: {}, port: {})", pid, existing_port).green()
             );
+            Report {
+                pid: Some(pid),
+                port: Some(existing_port),
+                root,
+                ..Report::new("stopped")
+            }
+            .emit();
         } else {
-            println!("{}", "No server running on port 8000.".yellow());
+            status!("{}", "No server running on port 8000.".yellow());
+            Report::new("not_running").emit();
         }
         return Ok(());
     }
@@ -246,125 +622,153 @@ async fn main() -> Result<()> {
     // 6. Handle Existing Server
     // =========================================================================
     if let Some((pid, existing_port)) = existing_server {
-        let full_url = format!("http://localhost:{}{}", existing_port, url_path_str);
+        // Build the URL from the running server's own options when it is one of ours
+        let existing_serve = running_server_args(pid)
+            .map(|a| a.serve)
+            .unwrap_or_default();
+        let full_url = existing_serve.url(existing_port, &url_path_str);
+        let existing_root = get_server_root(pid);
+        let existing_report = |action| Report {
+            pid: Some(pid),
+            port: Some(existing_port),
+            root: existing_root.clone(),
+            url: Some(full_url.clone()),
+            ..Report::new(action)
+        };
 
         // Default behavior: reuse existing server and open browser
-        // With -m/--menu flag: show interactive menu
-        if !args.menu {
-            println!(
+        // With -m/--menu flag (or --action): show interactive menu
+        if !args.menu && args.action.is_none() {
+            status!(
                 "{}",
                 format!("⚠ Reusing existing server (PID: {}, port: {})", pid, existing_port).yellow()
             );
-            open::that(&full_url)?;
-            println!("{}", format!("✓ Browser opened at {}", full_url).green());
+            launch.open_url(&full_url)?;
+            existing_report("reused").emit();
             return Ok(());
         }
 
         // -m/--menu flag: Show interactive menu
-        println!(
+        status!(
             "{}",
             "⚠ An HTTP server is already running!".yellow().bold()
         );
-        if let Some(dir) = get_process_cwd(pid) {
-            println!("{} {}", "Directory:".cyan(), dir.magenta());
+        if let Some(ref dir) = existing_root {
+            status!("{} {}", "Directory:".cyan(), dir.magenta());
         }
-        println!("{} {}", "PID:".cyan(), pid.to_string().magenta());
-        println!("{} {}", "Port:".cyan(), existing_port.to_string().magenta());
-        println!(
+        status!("{} {}", "PID:".cyan(), pid.to_string().magenta());
+        status!("{} {}", "Port:".cyan(), existing_port.to_string().magenta());
+        status!(
             "{} {}",
             "URL:".cyan(),
             full_url.blue().bold()
         );
-        println!();
+        for network_url in existing_serve.network_urls(existing_port, &url_path_str) {
+            status!("{} {}", "Network:".cyan(), network_url.blue().bold());
+        }
+        status!();
 
         // Interactive menu
-        let options = vec![
+        let network_urls = existing_serve.network_urls(existing_port, &url_path_str);
+        let mut options = vec![
             ExistingServerMenu::OpenBrowser,
             ExistingServerMenu::QuitServer,
             ExistingServerMenu::QuitAndRestart,
             ExistingServerMenu::Cancel,
         ];
+        if !network_urls.is_empty() {
+            options.push(ExistingServerMenu::ShowQrCode);
+        }
 
-        println!("{}", "What would you like to do?".bold());
-        let choice = Select::new("", options).prompt()?;
+        let choice = choose(options, args.action.map(ExistingServerMenu::from))?;
 
         match choice {
             ExistingServerMenu::OpenBrowser => {
-                open::that(&full_url)?;
-                println!(
-                    "{}",
-                    format!("✓ Browser opened at {}", full_url).green()
-                );
+                launch.without_prompt().open_url(&full_url)?;
+                existing_report("opened").emit();
             }
             ExistingServerMenu::QuitServer => {
                 kill_process(pid)?;
-                println!("{}", "✓ Server stopped successfully".green());
+                status!("{}", "✓ Server stopped successfully".green());
+                existing_report("stopped").emit();
             }
             ExistingServerMenu::QuitAndRestart => {
                 kill_process(pid)?;
-                println!("{}", "✓ Server stopped successfully".green());
-                println!();
+                status!("{}", "✓ Server stopped successfully".green());
+                status!();
 
                 // Wait for port to be released
                 tokio::time::sleep(std::time::Duration::from_millis(500)).await;
 
                 // Re-check for HTML files (we're restarting in current dir context)
-                println!(
+                status!(
                     "{}",
                     "━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━"
                         .cyan()
                         .bold()
                 );
-                println!(
+                status!(
                     "{} {}",
                     "Checking for HTML files in:".cyan(),
                     current_dir.display().to_string().magenta()
                 );
-                println!(
+                status!(
                     "{}",
                     "━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━"
                         .cyan()
                         .bold()
                 );
-                println!();
+                status!();
 
-                if !has_html_files(&current_dir) {
+                if !serving_archive && !has_site_files(&current_dir, &args.serve) {
                     eprintln!("{}", "✗ No HTML files found!".red().bold());
-                    std::process::exit(1);
+                    Report::fail("No HTML files found in current directory");
                 }
-                println!("{}", "✓ Found HTML files".green());
-                println!();
+                status!("{}", "✓ Found HTML files".green());
+                status!();
 
                 // Find new available port and start
                 let new_port = find_available_port(DEFAULT_PORT)?;
-                let new_url = format!("http://localhost:{}{}", new_port, url_path_str);
-                start_server(&server_dir, new_port, &new_url, args.prompt, args.foreground).await?;
+                start_server(&server_dir, new_port, &url_path_str, args.foreground, &launch, &args.serve).await?;
             }
             ExistingServerMenu::Cancel => {
-                println!("{}", "Cancelled - no changes made".yellow());
+                status!("{}", "Cancelled - no changes made".yellow());
+                existing_report("cancelled").emit();
+            }
+            ExistingServerMenu::ShowQrCode => {
+                let Some(network_url) = network_urls.first() else {
+                    bail!("The running server is only reachable from this machine (start it with --lan)");
+                };
+                status!("{} {}", "Network:".cyan(), network_url.blue().bold());
+                print_qr_code(network_url);
+                existing_report("qr").emit();
             }
         }
     } else {
         // =========================================================================
         // 7. No Existing Server - Start New One
         // =========================================================================
-        let full_url = format!("http://localhost:{}{}", port, url_path_str);
+        let full_url = args.serve.url(port, &url_path_str);
 
-        if args.menu {
+        if matches!(args.action, Some(MenuAction::Quit | MenuAction::Qr)) {
+            // --action quit/qr: nothing to quit or show
+            status!("{}", "No server running on port 8000.".yellow());
+            Report::new("not_running").emit();
+        } else if args.menu || args.action.is_some() {
             // -m/--menu flag: Show startup menu
-            println!("{}", "No server currently running.".cyan());
-            println!(
+            status!("{}", "No server currently running.".cyan());
+            status!(
                 "{} {}",
                 "Directory:".cyan(),
                 server_dir.display().to_string().magenta()
             );
-            println!("{} {}", "Port:".cyan(), port.to_string().magenta());
-            println!(
+            status!("{} {}", "Port:".cyan(), port.to_string().magenta());
+            status!(
                 "{} {}",
                 "URL:".cyan(),
                 full_url.blue().bold()
             );
-            println!();
+            status!();
 
             let options = vec![
                 StartupMenu::StartBackground,
@@ -372,29 +776,86 @@ async fn main() -> Result<()> {
                 StartupMenu::Cancel,
             ];
 
-            println!("{}", "What would you like to do?".bold());
-            let choice = Select::new("", options).prompt()?;
+            // --action open/restart starts the server (in foreground with -f)
+            let preselected = args.action.map(|action| match action {
+                MenuAction::Cancel => StartupMenu::Cancel,
+                _ if args.foreground => StartupMenu::StartForeground,
+                _ => StartupMenu::StartBackground,
+            });
+            let choice = choose(options, preselected)?;
 
             match choice {
                 StartupMenu::StartBackground => {
-                    start_server(&server_dir, port, &full_url, args.prompt, false).await?;
+                    start_server(&server_dir, port, &url_path_str, false, &launch, &args.serve).await?;
                 }
                 StartupMenu::StartForeground => {
-                    start_server(&server_dir, port, &full_url, args.prompt, true).await?;
+                    start_server(&server_dir, port, &url_path_str, true, &launch, &args.serve).await?;
                 }
                 StartupMenu::Cancel => {
-                    println!("{}", "Cancelled - no server started".yellow());
+                    status!("{}", "Cancelled - no server started".yellow());
+                    Report::new("cancelled").emit();
                 }
             }
         } else {
             // Default: start server based on -f flag
-            start_server(&server_dir, port, &full_url, args.prompt, args.foreground).await?;
+            start_server(&server_dir, port, &url_path_str, args.foreground, &launch, &args.serve).await?;
         }
     }
 
     Ok(())
 }
 
+/// Pick a menu option: the preselected --action if given, otherwise ask interactively
+///
+/// Refuses to show the menu when stdin is not a terminal, so scripts fail
+/// fast instead of hanging on input that will never come.
+fn choose<T: std::fmt::Display + Clone>(options: Vec<T>, preselected: Option<T>) -> Result<T> {
+    if let Some(choice) = preselected {
+        status!("{} {}", "Action:".cyan(), choice.to_string().magenta());
+        return Ok(choice);
+    }
+    if !std::io::stdin().is_terminal() {
+        bail!("stdin is not a terminal; use --action to choose a menu option non-interactively");
+    }
+
+    status!("{}", "What would you like to do?".bold());
+    Ok(Select::new("", options).prompt()?)
+}
+
+/// Site root of `hopen check` and `hopen sitemap`: the argument, then -r and
+/// HOPEN_SITE_HOME like the server, then the current directory
+fn command_root(root: Option<&PathBuf>, site_home: Option<&str>) -> Result<PathBuf> {
+    let root = match root {
+        Some(root) => root.clone(),
+        None => match site_home.map(String::from).or_else(|| env::var("HOPEN_SITE_HOME").ok()) {
+            Some(site_home) => PathBuf::from(site_home),
+            None => env::current_dir().context("Failed to get current directory")?,
+        },
+    };
+    if is_site_archive(&root) {
+        bail!("{} is an archive; unpack it to check or list its pages", root.display());
+    }
+    if !root.is_dir() {
+        bail!("{} is not a directory", root.display());
+    }
+    Ok(root)
+}
+
+/// Whether `path` is an archive the site is served from (zip, tar, WARC, MHTML)
+fn is_site_archive(path: &Path) -> bool {
+    archive::is_archive(path) || warc::is_warc(path) || mhtml::is_mhtml(path)
+}
+
+/// Check that there is something to serve: HTML files in the directory, or
+/// with --domains in one of its domain folders
+fn has_site_files(dir: &Path, serve: &ServeArgs) -> bool {
+    has_html_files(dir)
+        || (serve.domains.is_some()
+            && domains::domain_dirs(dir)
+                .iter()
+                .any(|name| has_html_files(&dir.join(name))))
+}
+
 /// Check if there are any HTML files in the directory
 fn has_html_files(dir: &Path) -> bool {
     if let Ok(entries) = fs::read_dir(dir) {
@@ -491,6 +952,71 @@ fn get_process_cwd(pid: u32) -> Option<String> {
     None
 }
 
+/// Parse the command line of a running hopen server with our own parser
+///
+/// Returns None when the process is gone or is not a hopen server.
+fn running_server_args(pid: u32) -> Option<Args> {
+    let s = System::new_all();
+    let process = s.process(Pid::from(pid as usize))?;
+    let cmd = process.cmd();
+    let is_hopen = cmd
+        .first()
+        .and_then(|exe| Path::new(exe).file_name())
+        .is_some_and(|name| name == "hopen");
+    if !is_hopen {
+        return None;
+    }
+    let mut args = Args::try_parse_from(cmd).ok()?;
+    let environ = |name: &str| {
+        process
+            .environ()
+            .iter()
+            .find_map(|var| var.strip_prefix(name)?.strip_prefix('='))
+            .map(str::to_string)
+    };
+    // Foreground servers generate their token themselves; it is only in
+    // the URL they recorded
+    args.serve.access_token = environ(auth::ACCESS_TOKEN_ENV).or_else(|| {
+        registry::running_servers()
+            .into_iter()
+            .find(|record| record.pid == pid)
+            .and_then(|record| auth::token_in_url(&record.url))
+    });
+    if args.serve.auth.is_none() {
+        args.serve.auth = environ(auth::CREDENTIALS_ENV);
+    }
+    Some(args)
+}
+
+/// Get the directory a server is serving
+///
+/// For our own background servers this is the --internal-dir argument; for
+/// anything else we fall back to the process's working directory.
+fn get_server_root(pid: u32) -> Option<String> {
+    running_server_args(pid)
+        .and_then(|a| a.internal_dir)
+        .or_else(|| get_process_cwd(pid))
+}
+
+/// Print a QR code of a URL in the terminal (for opening it on a phone)
+fn print_qr_code(url: &str) {
+    let code = match QrCode::new(url) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Failed to generate QR code: {}", e);
+            return;
+        }
+    };
+    // Inverted colors: light modules on the (usually dark) terminal background
+    let image = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    status!();
+    status!("{}", image);
+}
+
 /// Kill a process by PID
 fn kill_process(pid: u32) -> Result<()> {
     let s = System::new_all();
@@ -506,38 +1032,181 @@ fn kill_process(pid: u32) -> Result<()> {
     Ok(())
 }
 
-/// Run the warp HTTP server (used for both foreground and background modes)
-async fn run_server(root: &Path, port: u16) -> Result<()> {
-    // Set up Ctrl+C handler for graceful shutdown
-    let should_exit = Arc::new(AtomicBool::new(false));
-    let s_exit = should_exit.clone();
+/// How the final URL is handed over once a server is available
+#[derive(Debug, Clone)]
+struct BrowserLaunch {
+    browser: Option<String>,
+    prompt: bool,
+    assume_yes: bool,
+    no_open: bool,
+    print_url: bool,
+}
+
+impl BrowserLaunch {
+    /// The same launch settings, but opening without the -p prompt
+    /// (used when the user has already chosen "Open in browser" from a menu)
+    fn without_prompt(&self) -> Self {
+        Self {
+            prompt: false,
+            ..self.clone()
+        }
+    }
+
+    /// Print and/or open the URL according to --print-url, --no-open and -p
+    fn open_url(&self, url: &str) -> Result<()> {
+        if self.print_url {
+            println!("{}", url);
+        }
+        if self.no_open {
+            return Ok(());
+        }
+
+        if self.prompt && !self.assume_yes {
+            if !std::io::stdin().is_terminal() {
+                status!(
+                    "{}",
+                    "stdin is not a terminal; not opening browser (use --yes to open)".yellow()
+                );
+                return Ok(());
+            }
+            eprint!("{}", "Open in browser now? [y/N]: ".bold());
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input)?;
+            if !input.trim().eq_ignore_ascii_case("y") {
+                return Ok(());
+            }
+        }
 
-    ctrlc::set_handler(move || {
-        s_exit.store(true, Ordering::SeqCst);
-        std::process::exit(0);
-    })
-    .ok(); // Ignore error if handler already set
+        open_browser(url, self.browser.as_deref())?;
+        status!("{}", format!("✓ Browser opened at {}", url).green());
+        Ok(())
+    }
+}
 
-    // Serve files using warp
-    let route = warp::fs::dir(root.to_path_buf());
-    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port);
+/// Open a URL in the browser
+///
+/// Without a browser, the system default is used. A name (e.g. `firefox` or
+/// `Google Chrome`) is handed to the system opener; anything containing
+/// `{url}` is run as a shell command, with `{url}` replaced by the URL.
+fn open_browser(url: &str, browser: Option<&str>) -> Result<()> {
+    let Some(browser) = browser else {
+        open::that(url)?;
+        return Ok(());
+    };
 
-    warp::serve(route).run(addr).await;
+    if !browser.contains("{url}") {
+        open::with_detached(url, browser)
+            .with_context(|| format!("Failed to open browser '{}'", browser))?;
+        return Ok(());
+    }
 
+    let command_line = browser.replace("{url}", &shell_quote(url));
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&command_line)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run browser command '{}'", browser))?;
+
+    // Browsers may keep running, so only a command that fails right away
+    // is noticed
+    let deadline = std::time::Instant::now() + BROWSER_COMMAND_GRACE;
+    while std::time::Instant::now() < deadline {
+        if let Some(status) = child.try_wait()? {
+            if !status.success() {
+                bail!("Browser command '{}' failed ({})", browser, status);
+            }
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
     Ok(())
 }
 
+/// Quote a string for safe interpolation into a `sh -c` command line
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
 /// Start the HTTP server and open the browser
-async fn start_server(root: &Path, port: u16, url: &str, prompt: bool, foreground: bool) -> Result<()> {
-    println!("{}", "✓ All checks passed!".green().bold());
-    println!(
+async fn start_server(
+    root: &Path,
+    port: u16,
+    url_path: &str,
+    foreground: bool,
+    launch: &BrowserLaunch,
+    serve: &ServeArgs,
+) -> Result<()> {
+    let url = &serve.url(port, url_path);
+
+    status!("{}", "✓ All checks passed!".green().bold());
+    status!(
         "{} {}",
         "Starting HTTP server in:".cyan(),
         root.display().to_string().magenta()
     );
-    println!("{} {}", "Port:".cyan(), port.to_string().magenta());
-    println!("{} {}", "Access at:".cyan(), url.blue().bold());
-    println!();
+    status!("{} {}", "Port:".cyan(), port.to_string().magenta());
+    status!("{} {}", "Access at:".cyan(), url.blue().bold());
+    let network_urls = serve.network_urls(port, url_path);
+    for network_url in &network_urls {
+        status!("{} {}", "Network:".cyan(), network_url.blue().bold());
+    }
+    if !serve.is_loopback_only() {
+        status!(
+            "{}",
+            "⚠ The server is reachable from other machines on your network".yellow()
+        );
+    }
+    if serve.auth.is_some() {
+        status!("{} {}", "Auth:".cyan(), "HTTP basic auth".magenta());
+    } else if serve.uses_token() {
+        status!("{} {}", "Auth:".cyan(), "access token (included in the URLs above)".magenta());
+    } else if !serve.is_loopback_only() {
+        status!(
+            "{}",
+            "⚠ No authentication: anyone on your network can read the site".yellow()
+        );
+    }
+    if let Some(mode) = serve.domains {
+        let domains = domains::Domains::new(mode, root, serve.scheme(), port);
+        if domains.names().is_empty() {
+            status!(
+                "{}",
+                "⚠ No domain folders (like www.example.com/) found in the site root".yellow()
+            );
+        }
+        for name in domains.names() {
+            status!(
+                "{} {}",
+                format!("{}:", name).cyan(),
+                format!("{}/", domains.local_base(name)).blue().bold()
+            );
+        }
+    }
+    for origin in &serve.rewrite_origin {
+        status!("{} {} {}", "Rewriting links:".cyan(), origin.magenta(), "→ local paths".cyan());
+    }
+    // Prepare the certificate up front so problems show up here rather than
+    // only in the background server's log
+    if let Some((cert, _)) = serve.tls_files()? {
+        if serve.cert.is_none() {
+            status!(
+                "{} {}",
+                "Using self-signed certificate:".cyan(),
+                cert.display().to_string().magenta()
+            );
+            status!(
+                "{}",
+                "Your browser will ask you to accept it the first time.".yellow()
+            );
+        }
+    }
+    if let Some(network_url) = network_urls.first() {
+        print_qr_code(network_url);
+    }
+    status!();
 
     // Check if root exists
     if !root.exists() {
@@ -550,34 +1219,36 @@ async fn start_server(root: &Path, port: u16, url: &str, prompt: bool, foregroun
         // =========================================================================
 
         // Open browser: auto by default, prompt with -p flag
-        if prompt {
-            print!("{}", "Open in browser now? [y/N]: ".bold());
-            use std::io::Write;
-            std::io::stdout().flush()?;
-            let mut input = String::new();
-            std::io::stdin().read_line(&mut input)?;
-            if input.trim().eq_ignore_ascii_case("y") {
-                open::that(url)?;
-                println!("{}", format!("✓ Browser opened at {}", url).green());
-            }
+        if launch.prompt {
+            launch.open_url(url)?;
         } else {
             // Default: auto-open browser after a short delay
             let url_string = url.to_string();
+            let launch = launch.clone();
             tokio::spawn(async move {
                 tokio::time::sleep(std::time::Duration::from_millis(300)).await;
-                if let Err(e) = open::that(&url_string) {
+                if let Err(e) = launch.open_url(&url_string) {
                     eprintln!("Failed to open browser: {}", e);
                 }
             });
-            println!("{}", format!("✓ Browser opened at {}", url).green());
         }
 
-        println!(
+        status!(
             "{}",
             "Server running (press Ctrl+C to stop)".cyan()
         );
+        Report {
+            pid: Some(std::process::id()),
+            port: Some(port),
+            root: Some(root.display().to_string()),
+            url: Some(url.to_string()),
+            foreground: true,
+            ..Report::new("started")
+        }
+        .emit();
 
-        run_server(root, port).await?;
+        ServerRecord::new(std::process::id(), port, root, url, None).register_or_warn();
+        run_server(root, port, serve, None).await?;
     } else {
         // =========================================================================
         // Background Mode (default): Spawn ourselves as a background server
@@ -585,7 +1256,9 @@ async fn start_server(root: &Path, port: u16, url: &str, prompt: bool, foregroun
         // We spawn a new instance of ourselves with --internal-serve flag
         // This uses native Rust/warp instead of Python
 
-        let log_file = format!("/tmp/hopen-server-{}.log", std::process::id());
+        logs::prune_logs();
+        let log_file = registry::log_path(port)?;
+        let log = logs::open_log_file(&log_file, serve.log_max_bytes())?;
         let exe_path = env::current_exe().context("Failed to get current executable path")?;
 
         // Spawn ourselves with internal-serve flag
@@ -597,9 +1270,14 @@ async fn start_server(root: &Path, port: u16, url: &str, prompt: bool, foregroun
             .arg(port.to_string())
             .arg("--internal-dir")
             .arg(root.to_string_lossy().to_string())
+            .arg("--internal-log")
+            .arg(&log_file)
+            .args(serve.to_cli_args())
+            .envs(serve.access_token.iter().map(|t| (auth::ACCESS_TOKEN_ENV, t)))
+            .envs(serve.auth.iter().map(|c| (auth::CREDENTIALS_ENV, c)))
             .stdin(std::process::Stdio::null())
-            .stdout(std::fs::File::create(&log_file)?)
-            .stderr(std::fs::File::create(&log_file)?)
+            .stdout(log.try_clone()?)
+            .stderr(log)
             .spawn()
             .context("Failed to start background server")?;
 
@@ -611,39 +1289,40 @@ async fn start_server(root: &Path, port: u16, url: &str, prompt: bool, foregroun
         // Verify the server started
         if !is_port_in_use(port) {
             eprintln!("{}", "✗ Failed to start server".red().bold());
-            eprintln!("{} {}", "Check logs:".yellow(), log_file.cyan());
-            std::process::exit(1);
+            eprintln!("{} {}", "Check logs:".yellow(), log_file.display().to_string().cyan());
+            Report::fail("Failed to start server");
         }
 
-        println!(
+        status!(
             "{} {}",
             "✓ Server started successfully!".green().bold(),
             format!("(PID: {})", pid).cyan()
         );
-        println!(
+        status!(
             "{} {}",
             "To stop the server, run:".yellow(),
             format!("kill {}", pid).cyan()
         );
-        println!("{} {}", "Logs:".cyan(), log_file.magenta());
-        println!();
+        ServerRecord::new(pid, port, root, url, Some(log_file.clone())).register_or_warn();
+        status!(
+            "{} {}",
+            "Logs:".cyan(),
+            format!("hopen logs {} (or {})", port, log_file.display()).magenta()
+        );
+        status!();
 
         // Open browser: auto by default, prompt with -p flag
-        if prompt {
-            print!("{}", "Open in browser now? [y/N]: ".bold());
-            use std::io::Write;
-            std::io::stdout().flush()?;
-            let mut input = String::new();
-            std::io::stdin().read_line(&mut input)?;
-            if input.trim().eq_ignore_ascii_case("y") {
-                open::that(url)?;
-                println!("{}", format!("✓ Browser opened at {}", url).green());
-            }
-        } else {
-            // Default: auto-open browser
-            open::that(url)?;
-            println!("{}", format!("✓ Browser opened at {}", url).green());
+        launch.open_url(url)?;
+
+        Report {
+            pid: Some(pid),
+            port: Some(port),
+            root: Some(root.display().to_string()),
+            url: Some(url.to_string()),
+            log: Some(log_file.display().to_string()),
+            ..Report::new("started")
         }
+        .emit();
     }
 
     Ok(())
@@ -0,0 +1,326 @@
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use warp::http::{header, HeaderValue, Response};
+use warp::hyper::Body;
+use warp::path::FullPath;
+use warp::{Filter, Rejection};
+
+use crate::pages;
+
+/// Characters that may follow a URL in HTML or CSS
+const URL_END: &[u8] = b"\"'()<> \t\r\n";
+
+/// Whether `path` is an MHTML file (`.mhtml` or `.mht`)
+pub fn is_mhtml(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let name = name.to_ascii_lowercase();
+    path.is_file() && (name.ends_with(".mhtml") || name.ends_with(".mht"))
+}
+
+/// One MIME part of the archive, decoded
+struct Part {
+    content_type: String,
+    location: Option<String>,
+    content_id: Option<String>,
+    body: Vec<u8>,
+}
+
+/// A page saved by a browser as a single MHTML file
+///
+/// The MIME parts are decoded in memory when the server starts. The main
+/// document is served at `/`, and every part at a local URL: the path of
+/// its Content-Location for the page's own host, `/<host>/...` for other
+/// hosts and `/__mhtml/<n>` for parts without location. References to
+/// parts in HTML and CSS (`cid:` URLs and Content-Locations) are rewritten
+/// to these URLs.
+pub struct MhtmlArchive {
+    parts: Vec<Part>,
+    /// Local URL (path with query) to part index
+    urls: HashMap<String, usize>,
+    main: usize,
+}
+
+impl MhtmlArchive {
+    pub fn open(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&data).with_context(|| format!("Failed to read MHTML file {}", path.display()))
+    }
+
+    fn parse(data: &[u8]) -> Result<Self> {
+        let (headers, body) = split_part(data);
+        let content_type = headers.get("content-type").cloned().unwrap_or_default();
+        let Some(boundary) = param(&content_type, "boundary") else {
+            bail!("not a multipart MHTML file (no boundary)");
+        };
+        let parts: Vec<Part> = split_multipart(body, &boundary)
+            .into_iter()
+            .map(decode_part)
+            .collect();
+        if parts.is_empty() {
+            bail!("no parts found");
+        }
+
+        // The snapshot's own URL names the main document; else the first HTML part
+        let snapshot = headers.get("snapshot-content-location");
+        let main = parts
+            .iter()
+            .position(|p| p.location.is_some() && p.location.as_ref() == snapshot)
+            .or_else(|| parts.iter().position(|p| p.content_type.starts_with("text/html")))
+            .unwrap_or(0);
+        let main_host = parts[main].location.as_deref().and_then(host_and_path).map(|(h, _)| h);
+
+        let mut archive = Self {
+            parts,
+            urls: HashMap::new(),
+            main,
+        };
+        let mut references = Vec::new();
+        for (index, part) in archive.parts.iter().enumerate() {
+            let local = match part.location.as_deref().and_then(host_and_path) {
+                Some((host, path)) if Some(&host) == main_host.as_ref() => path,
+                Some((host, path)) => format!("/{}{}", host, path),
+                None => format!("/__mhtml/{}", index),
+            };
+            if let Some(ref location) = part.location {
+                references.push((location.clone(), local.clone()));
+            }
+            if let Some(ref id) = part.content_id {
+                references.push((format!("cid:{}", id), local.clone()));
+            }
+            archive.urls.entry(local).or_insert(index);
+        }
+        archive.rewrite_references(references);
+        Ok(archive)
+    }
+
+    /// Point references between the parts at their local URLs
+    fn rewrite_references(&mut self, mut references: Vec<(String, String)>) {
+        // HTML spells `&` in URLs as `&amp;`
+        let escaped: Vec<(String, String)> = references
+            .iter()
+            .filter(|(from, _)| from.contains('&'))
+            .map(|(from, to)| (from.replace('&', "&amp;"), to.replace('&', "&amp;")))
+            .collect();
+        references.extend(escaped);
+        // The longest first, so a page's URL doesn't match a prefix of another
+        references.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+        for part in &mut self.parts {
+            let mime = part.content_type.split(';').next().unwrap_or("").trim();
+            if matches!(mime, "text/html" | "application/xhtml+xml" | "text/css") {
+                part.body = replace_urls(&part.body, &references);
+            }
+        }
+    }
+
+    fn respond(&self, path: &str) -> Option<Response<Body>> {
+        let index = if path == "/" {
+            *self.urls.get("/").unwrap_or(&self.main)
+        } else {
+            *self.urls.get(path)?
+        };
+        let part = &self.parts[index];
+        let content_type = HeaderValue::from_str(&part.content_type)
+            .unwrap_or_else(|_| HeaderValue::from_static("application/octet-stream"));
+        Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, part.body.len())
+            .body(Body::from(part.body.clone()))
+            .ok()
+    }
+}
+
+/// Filter serving the parts of an MHTML file
+///
+/// Rejects with a 404 for URLs that aren't parts of it.
+pub fn filter(
+    mhtml: Arc<MhtmlArchive>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    warp::path::full()
+        .and(pages::query())
+        .and_then(move |path: FullPath, query: String| {
+            let mhtml = mhtml.clone();
+            async move {
+                let mut path = path.as_str().to_string();
+                if !query.is_empty() {
+                    path.push('?');
+                    path.push_str(&query);
+                }
+                mhtml.respond(&path).ok_or_else(warp::reject::not_found)
+            }
+        })
+}
+
+/// Headers (lowercased names, folded lines joined) and body of a MIME entity
+fn split_part(data: &[u8]) -> (HashMap<String, String>, &[u8]) {
+    // The first blank line, with either line ending
+    let crlf = find(data, b"\r\n\r\n").map(|at| (at, 4));
+    let lf = find(data, b"\n\n").map(|at| (at, 2));
+    let (head, body) = match [crlf, lf].into_iter().flatten().min() {
+        Some((at, len)) => (&data[..at], &data[at + len..]),
+        None => (data, &data[data.len()..]),
+    };
+    let mut headers: HashMap<String, String> = HashMap::new();
+    let mut last: Option<String> = None;
+    for line in String::from_utf8_lossy(head).lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some(value) = last.as_ref().and_then(|name| headers.get_mut(name)) {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim().to_ascii_lowercase();
+            headers.insert(name.clone(), value.trim().to_string());
+            last = Some(name);
+        }
+    }
+    (headers, body)
+}
+
+/// The bodies between `--boundary` delimiter lines
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+    let mut rest = body;
+    let Some(first) = find(rest, &delimiter) else {
+        return parts;
+    };
+    rest = &rest[first + delimiter.len()..];
+    // After each delimiter: `--` ends the multipart, else the part follows
+    while !rest.starts_with(b"--") {
+        let start = rest.iter().position(|&b| b == b'\n').map_or(rest.len(), |i| i + 1);
+        rest = &rest[start..];
+        let end = find(rest, &delimiter).unwrap_or(rest.len());
+        let mut part = &rest[..end];
+        // The line break before the delimiter belongs to it
+        part = part.strip_suffix(b"\n").unwrap_or(part);
+        part = part.strip_suffix(b"\r").unwrap_or(part);
+        parts.push(part);
+        if end == rest.len() {
+            break;
+        }
+        rest = &rest[end + delimiter.len()..];
+    }
+    parts
+}
+
+fn decode_part(data: &[u8]) -> Part {
+    let (headers, body) = split_part(data);
+    let encoding = headers
+        .get("content-transfer-encoding")
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    let body = match encoding.as_str() {
+        "base64" => {
+            let compact: Vec<u8> = body.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect();
+            base64::engine::general_purpose::STANDARD
+                .decode(compact)
+                .unwrap_or_default()
+        }
+        "quoted-printable" => decode_quoted_printable(body),
+        _ => body.to_vec(),
+    };
+    Part {
+        content_type: headers
+            .get("content-type")
+            .cloned()
+            .unwrap_or_else(|| "application/octet-stream".to_string()),
+        location: headers.get("content-location").cloned(),
+        content_id: headers
+            .get("content-id")
+            .map(|id| id.trim_start_matches('<').trim_end_matches('>').to_string()),
+        body,
+    }
+}
+
+fn decode_quoted_printable(body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len());
+    let mut i = 0;
+    while i < body.len() {
+        if body[i] != b'=' {
+            out.push(body[i]);
+            i += 1;
+            continue;
+        }
+        // Soft line break
+        if body[i + 1..].starts_with(b"\r\n") {
+            i += 3;
+        } else if body[i + 1..].starts_with(b"\n") {
+            i += 2;
+        } else if let Some(byte) = body
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(b'=');
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Value of a parameter in a header like `multipart/related; boundary="x"`
+fn param(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|p| {
+        let (key, val) = p.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| val.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Host and path (with query) of an http(s) URL
+fn host_and_path(url: &str) -> Option<(String, String)> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let rest = rest.split('#').next().unwrap_or(rest);
+    let split = rest.find(['/', '?']).unwrap_or(rest.len());
+    let (host, path) = rest.split_at(split);
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+    Some((host.to_ascii_lowercase(), path))
+}
+
+/// Replace whole URLs (followed by a quote, bracket, space or the end)
+fn replace_urls(content: &[u8], references: &[(String, String)]) -> Vec<u8> {
+    let first_bytes: Vec<u8> = references.iter().filter_map(|(from, _)| from.bytes().next()).collect();
+    let mut out = Vec::with_capacity(content.len());
+    let mut i = 0;
+    'scan: while i < content.len() {
+        if !first_bytes.contains(&content[i]) {
+            out.push(content[i]);
+            i += 1;
+            continue;
+        }
+        for (from, to) in references {
+            let from = from.as_bytes();
+            let end = i + from.len();
+            if content[i..].starts_with(from)
+                && content.get(end).is_none_or(|c| URL_END.contains(c))
+            {
+                out.extend_from_slice(to.as_bytes());
+                i = end;
+                continue 'scan;
+            }
+        }
+        out.push(content[i]);
+        i += 1;
+    }
+    out
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
@@ -0,0 +1,246 @@
+use percent_encoding::percent_decode_str;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use warp::http::{HeaderMap, Response};
+use warp::hyper::Body;
+use warp::path::FullPath;
+use warp::{Filter, Rejection};
+
+use crate::{files, pages};
+use crate::policy::ServePolicy;
+
+/// How a mirroring tool names the file for a URL with a query string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameScheme {
+    /// SiteSucker: `page.php%3Fid=3.html`, `page-id=3.html`
+    SiteSucker,
+    /// wget: `page.php?id=3` (`.html` added with -E; `@` instead of `?`
+    /// with --restrict-file-names=windows)
+    Wget,
+    /// HTTrack: hashed names (`page3b9e.html`) looked up in `hts-cache/new.txt`
+    HtTrack,
+    /// A file name template, e.g. `{dir}{stem}_{query}{ext}`
+    Pattern(String),
+}
+
+/// Template placeholders, for `/docs/page.php?id=3`:
+/// `{path}` docs/page.php, `{dir}` docs/, `{name}` page.php, `{stem}` page,
+/// `{ext}` .php and `{query}` id=3
+const PLACEHOLDERS: &[&str] = &["{path}", "{dir}", "{name}", "{stem}", "{ext}", "{query}"];
+
+const SITESUCKER_PATTERNS: &[&str] = &[
+    "{path}%3F{query}.html",
+    "{path}%3F{query}",
+    "{dir}{stem}-{query}.html",
+    "{dir}{stem}-{query}{ext}",
+];
+
+const WGET_PATTERNS: &[&str] = &[
+    "{path}?{query}",
+    "{path}?{query}.html",
+    "{path}@{query}",
+    "{path}@{query}.html",
+];
+
+/// Validate a `--mirror-names` value
+pub fn validate_scheme(value: &str) -> Result<String, String> {
+    parse_scheme(value).map(|_| value.to_string())
+}
+
+/// Parse a `--mirror-names` value: a tool name, `all`, `none` or a template
+pub fn parse_scheme(value: &str) -> Result<Vec<NameScheme>, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "sitesucker" => Ok(vec![NameScheme::SiteSucker]),
+        "wget" => Ok(vec![NameScheme::Wget]),
+        "httrack" => Ok(vec![NameScheme::HtTrack]),
+        "all" => Ok(default_schemes()),
+        "none" => Ok(Vec::new()),
+        _ if value.contains("{query}") => {
+            let mut rest = value.to_string();
+            for placeholder in PLACEHOLDERS {
+                rest = rest.replace(placeholder, "");
+            }
+            if rest.contains('{') || rest.contains('}') {
+                return Err(format!(
+                    "unknown placeholder; use {}",
+                    PLACEHOLDERS.join(", ")
+                ));
+            }
+            Ok(vec![NameScheme::Pattern(value.to_string())])
+        }
+        _ => Err(
+            "expected sitesucker, wget, httrack, all, none or a template containing {query}"
+                .to_string(),
+        ),
+    }
+}
+
+/// Schemes used when `--mirror-names` isn't given
+pub fn default_schemes() -> Vec<NameScheme> {
+    vec![NameScheme::SiteSucker, NameScheme::Wget, NameScheme::HtTrack]
+}
+
+/// Finds the file a mirroring tool saved for a URL with a query string
+///
+/// `warp::fs::dir` ignores the query, so `/page.php?id=3` would either be a
+/// 404 or (worse) serve `page.php` for every id.
+#[derive(Debug, Clone, Default)]
+pub struct MirrorNames {
+    patterns: Vec<String>,
+    /// HTTrack's URL (`/path?query`, also `/host/path?query`) to local file
+    httrack: HashMap<String, PathBuf>,
+}
+
+impl MirrorNames {
+    pub fn new(root: &Path, schemes: &[NameScheme]) -> Self {
+        let mut names = Self::default();
+        for scheme in schemes {
+            match scheme {
+                NameScheme::SiteSucker => {
+                    names.patterns.extend(SITESUCKER_PATTERNS.iter().map(|p| p.to_string()))
+                }
+                NameScheme::Wget => {
+                    names.patterns.extend(WGET_PATTERNS.iter().map(|p| p.to_string()))
+                }
+                NameScheme::HtTrack => names.httrack = load_httrack_index(root),
+                NameScheme::Pattern(pattern) => names.patterns.push(pattern.clone()),
+            }
+        }
+        names.patterns.dedup();
+        names
+    }
+
+    fn is_empty(&self) -> bool {
+        self.patterns.is_empty() && self.httrack.is_empty()
+    }
+
+    /// Candidate files (relative to the root) for a request path and raw query
+    fn candidates(&self, path: &str, query: &str) -> Vec<PathBuf> {
+        let mut candidates = Vec::new();
+        if let Some(file) = self.httrack.get(&format!("{}?{}", path, query)) {
+            candidates.push(file.clone());
+        }
+
+        let decoded = percent_decode_str(path.trim_start_matches('/'))
+            .decode_utf8_lossy()
+            .into_owned();
+        // A directory URL is saved as its index page
+        let file_path = if decoded.is_empty() || decoded.ends_with('/') {
+            format!("{}index.html", decoded)
+        } else {
+            decoded
+        };
+        let (dir, name) = match file_path.rfind('/') {
+            Some(slash) => file_path.split_at(slash + 1),
+            None => ("", file_path.as_str()),
+        };
+        let (stem, ext) = match name.rfind('.') {
+            Some(dot) if dot > 0 => name.split_at(dot),
+            _ => (name, ""),
+        };
+
+        for pattern in &self.patterns {
+            let candidate = pattern
+                .replace("{path}", &file_path)
+                .replace("{dir}", dir)
+                .replace("{name}", name)
+                .replace("{stem}", stem)
+                .replace("{ext}", ext)
+                .replace("{query}", query);
+            candidates.push(PathBuf::from(candidate));
+        }
+        candidates
+    }
+
+    /// The first candidate that exists under `root` and may be served
+    pub fn resolve(&self, root: &Path, policy: &ServePolicy, path: &str, query: &str) -> Option<PathBuf> {
+        self.candidates(path, query).into_iter().find(|candidate| {
+            let segments: Vec<&str> = candidate
+                .iter()
+                .filter_map(|s| s.to_str())
+                .collect();
+            // Queries come from the URL: never step out of the root
+            candidate.is_relative()
+                && !segments.contains(&"..")
+                && root.join(candidate).is_file()
+                && policy.check_segments(&segments).is_ok()
+        })
+    }
+}
+
+/// Filter serving query-string URLs from the files mirroring tools saved them as
+///
+/// Rejects (so plain file serving takes over) when there is no query or no
+/// matching file.
+pub fn filter(
+    root: &Path,
+    names: Arc<MirrorNames>,
+    policy: Arc<ServePolicy>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let root = Arc::new(root.to_path_buf());
+    warp::path::full()
+        // Rejecting for a missing query would outrank the fallback's 404
+        .and(pages::query())
+        .and(warp::header::headers_cloned())
+        .and_then(move |path: FullPath, query: String, headers: HeaderMap| {
+            let root = root.clone();
+            let names = names.clone();
+            let policy = policy.clone();
+            async move {
+                if query.is_empty() || names.is_empty() {
+                    return Err(warp::reject::not_found());
+                }
+                match names.resolve(&root, &policy, path.as_str(), &query) {
+                    Some(file) => {
+                        Ok(files::file_response(&root.join(file), path.as_str(), &headers).await)
+                    }
+                    None => Err(warp::reject::not_found()),
+                }
+            }
+        })
+}
+
+/// Read HTTrack's download log (`hts-cache/new.txt`), in the root or its parent
+///
+/// Its tab-separated lines end with the URL and the local file, relative to
+/// the project folder (which holds one folder per host).
+fn load_httrack_index(root: &Path) -> HashMap<String, PathBuf> {
+    let mut index = HashMap::new();
+    let project = [Some(root), root.parent()]
+        .into_iter()
+        .flatten()
+        .find(|dir| dir.join("hts-cache").join("new.txt").is_file());
+    let Some(project) = project else {
+        return index;
+    };
+    let Ok(log) = fs::read_to_string(project.join("hts-cache").join("new.txt")) else {
+        return index;
+    };
+    let project = project.canonicalize().unwrap_or_else(|_| project.to_path_buf());
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+
+    for line in log.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let (Some(url), Some(local)) = (fields.get(7), fields.get(8)) else {
+            continue;
+        };
+        let Some((host_and_path, query)) = url
+            .split_once("://")
+            .map_or(*url, |(_, rest)| rest)
+            .split_once('?')
+        else {
+            continue;
+        };
+        let (host, path) = host_and_path
+            .split_once('/')
+            .map_or((host_and_path, ""), |(host, path)| (host, path));
+        let Ok(file) = project.join(local).strip_prefix(&root).map(Path::to_path_buf) else {
+            continue;
+        };
+        index.insert(format!("/{}?{}", path, query), file.clone());
+        index.insert(format!("/{}/{}?{}", host, path, query), file);
+    }
+    index
+}
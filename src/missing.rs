@@ -0,0 +1,163 @@
+use anyhow::Result;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use warp::http::{header, Response, StatusCode};
+use warp::hyper::Body;
+
+use crate::access_log::{self, RequestInfo};
+use crate::pages;
+use crate::reports::{self, ReportSnapshot, ReportStore};
+
+/// Most referrers kept per path
+const MAX_REFERRERS: usize = 20;
+
+/// A path that was requested but not found
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MissingResource {
+    pub path: String,
+    /// How often it was requested
+    pub count: u64,
+    /// Pages that referred to it (from the Referer header), with counts
+    pub referrers: BTreeMap<String, u64>,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+/// The 404s seen by one server, most requested first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MissingSnapshot {
+    pub port: u16,
+    pub root: PathBuf,
+    pub missing: Vec<MissingResource>,
+}
+
+/// Aggregates the 404s of a running server by path and referrer
+pub type MissingReport = ReportStore<MissingSnapshot>;
+
+impl ReportSnapshot for MissingSnapshot {
+    type Entry = MissingResource;
+    const NAME: &'static str = "missing";
+    const TITLE: &'static str = "Missing resources";
+
+    fn new(port: u16, root: PathBuf, missing: Vec<MissingResource>) -> Self {
+        MissingSnapshot {
+            port,
+            root,
+            missing,
+        }
+    }
+
+    fn count(resource: &MissingResource) -> u64 {
+        resource.count
+    }
+
+    fn render_html(&self) -> String {
+        render_html(self)
+    }
+}
+
+/// Count the request if it was not found
+pub fn record(report: &Arc<MissingReport>, info: &RequestInfo, response: &Response<Body>) {
+    if response.status() != StatusCode::NOT_FOUND {
+        return;
+    }
+    let now = access_log::timestamp();
+    let referer = info.header(header::REFERER);
+    report.record(
+        info.path(),
+        || MissingResource {
+            path: info.path().to_string(),
+            count: 0,
+            referrers: BTreeMap::new(),
+            first_seen: now.clone(),
+            last_seen: now.clone(),
+        },
+        |resource| {
+            resource.count += 1;
+            resource.last_seen = now.clone();
+            if let Some(referer) = referer {
+                if resource.referrers.len() < MAX_REFERRERS
+                    || resource.referrers.contains_key(referer)
+                {
+                    *resource.referrers.entry(referer.to_string()).or_default() += 1;
+                }
+            }
+        },
+    );
+}
+
+/// Read the saved report of the server on a port
+pub fn load(port: u16) -> Result<MissingSnapshot> {
+    reports::load(port)
+}
+
+fn render_html(snapshot: &MissingSnapshot) -> String {
+    if snapshot.missing.is_empty() {
+        return "<p>No missing resources so far.</p>".to_string();
+    }
+    let mut html = format!(
+        "<p>{} paths were not found under <code>{}</code>. \
+         <a href=\"?format=json\">JSON</a></p>\n\
+         <table>\n<tr><th>Requests</th><th>Path</th><th>Referred from</th></tr>\n",
+        snapshot.missing.len(),
+        pages::escape(&snapshot.root.display().to_string())
+    );
+    for resource in &snapshot.missing {
+        let referrers = if resource.referrers.is_empty() {
+            "<span class=\"muted\">(no referrer)</span>".to_string()
+        } else {
+            resource
+                .referrers
+                .iter()
+                .map(|(referer, count)| {
+                    let referer_html = pages::escape(referer);
+                    // Only link real pages; the header is client-controlled
+                    let link = if referer.starts_with("http://") || referer.starts_with("https://") {
+                        format!("<a href=\"{0}\">{0}</a>", referer_html)
+                    } else {
+                        referer_html
+                    };
+                    format!("{} <span class=\"muted\">({})</span>", link, count)
+                })
+                .collect::<Vec<_>>()
+                .join("<br>")
+        };
+        html.push_str(&format!(
+            "<tr><td class=\"count\">{}</td><td><code>{}</code></td><td>{}</td></tr>\n",
+            resource.count,
+            pages::escape(&resource.path),
+            referrers
+        ));
+    }
+    html.push_str("</table>");
+    html
+}
+
+/// Print a report for `hopen report missing`
+pub fn print(snapshot: &MissingSnapshot) {
+    if snapshot.missing.is_empty() {
+        println!(
+            "{}",
+            format!("No missing resources on port {}", snapshot.port).green()
+        );
+        return;
+    }
+    println!(
+        "{} {}",
+        format!("Missing resources on port {}:", snapshot.port).bold(),
+        snapshot.root.display().to_string().magenta()
+    );
+    for resource in &snapshot.missing {
+        println!(
+            "{:>6}  {}",
+            resource.count.to_string().yellow(),
+            resource.path.cyan()
+        );
+        for (referer, count) in &resource.referrers {
+            println!("{:>6}    {} {}", "", "←".dimmed(), format!("{} ({})", referer, count).dimmed());
+        }
+    }
+}
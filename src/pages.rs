@@ -0,0 +1,80 @@
+use serde::Serialize;
+use std::convert::Infallible;
+use warp::http::{header, Response};
+use warp::hyper::Body;
+use warp::Filter;
+
+/// First path segment of hopen's own pages (e.g. `/__hopen/missing`)
+///
+/// Chosen so it is unlikely to clash with anything in a real site.
+pub const PREFIX: &str = "__hopen";
+
+/// Escape text for inclusion in HTML
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A minimal standalone HTML page
+pub fn html(title: &str, body: &str) -> Response<Body> {
+    let page = format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{title} - hopen</title>
+<style>
+body {{ font-family: system-ui, sans-serif; margin: 2em; color: #222; }}
+table {{ border-collapse: collapse; }}
+th, td {{ text-align: left; padding: 0.3em 0.8em; border-bottom: 1px solid #ddd; vertical-align: top; }}
+td.count {{ text-align: right; }}
+code {{ word-break: break-all; }}
+.muted {{ color: #888; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+{body}
+</body>
+</html>
+",
+        title = escape(title),
+        body = body
+    );
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::from(page))
+        .unwrap_or_default()
+}
+
+/// A JSON response
+pub fn json<T: Serialize>(value: &T) -> Response<Body> {
+    let body = serde_json::to_string_pretty(value).unwrap_or_default();
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::from(body))
+        .unwrap_or_default()
+}
+
+/// The raw query string of a request, empty when it has none
+pub fn query() -> impl Filter<Extract = (String,), Error = Infallible> + Clone {
+    warp::query::raw().or(warp::any().map(String::new)).unify()
+}
+
+/// Whether the query asks for JSON (`?format=json`)
+pub fn wants_json(query: &str) -> bool {
+    query.split('&').any(|pair| pair == "format=json")
+}
@@ -0,0 +1,125 @@
+use percent_encoding::percent_decode_str;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use warp::http::{header, Response, StatusCode};
+use warp::hyper::Body;
+use warp::path::FullPath;
+use warp::{Filter, Rejection};
+
+/// Dotfiles served even though hidden files are otherwise refused
+pub const DEFAULT_ALLOWED_DOTFILES: &[&str] = &[".well-known"];
+
+/// What may be served from under the site root
+///
+/// Hidden files (`.git`, `.env`, ...) are refused unless allow-listed, and
+/// symlinks are only followed while they stay inside the root.
+#[derive(Debug, Clone)]
+pub struct ServePolicy {
+    /// Canonical site root
+    root: PathBuf,
+    allowed_dotfiles: Vec<String>,
+    follow_symlinks: bool,
+}
+
+/// Why a path may not be served
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denial {
+    /// A path segment is a hidden file that isn't allow-listed
+    Hidden,
+    /// The path resolves (through a symlink) outside the site root
+    OutsideRoot,
+}
+
+impl ServePolicy {
+    pub fn new(root: &Path, allowed_dotfiles: &[String], follow_symlinks: bool) -> Self {
+        let mut allowed: Vec<String> = DEFAULT_ALLOWED_DOTFILES
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        allowed.extend(allowed_dotfiles.iter().cloned());
+        Self {
+            root: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+            allowed_dotfiles: allowed,
+            follow_symlinks,
+        }
+    }
+
+    /// Check a path relative to the root, given as decoded segments
+    pub fn check_segments<S: AsRef<str>>(&self, segments: &[S]) -> Result<(), Denial> {
+        let hidden = segments.iter().any(|segment| {
+            let segment = segment.as_ref();
+            segment.starts_with('.')
+                && segment != "."
+                && segment != ".."
+                && !self.allowed_dotfiles.iter().any(|a| a == segment)
+        });
+        if hidden {
+            return Err(Denial::Hidden);
+        }
+
+        if !self.follow_symlinks {
+            let mut path = self.root.clone();
+            path.extend(segments.iter().map(|s| s.as_ref()));
+            self.check_inside_root(&path)?;
+            // A directory is served through its index file, which may itself
+            // be a link
+            if path.is_dir() {
+                self.check_inside_root(&path.join("index.html"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuse existing paths that resolve outside the root
+    ///
+    /// Paths that don't exist pass; serving them simply fails with a 404.
+    fn check_inside_root(&self, path: &Path) -> Result<(), Denial> {
+        match path.canonicalize() {
+            Ok(resolved) if !resolved.starts_with(&self.root) => Err(Denial::OutsideRoot),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check a request path (percent-encoded, starting with `/`)
+    fn check_request(&self, path: &str) -> Result<(), Rejection> {
+        let segments: Vec<String> = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+            .collect();
+        match self.check_segments(&segments) {
+            Ok(()) => Ok(()),
+            // Pretend hidden files don't exist rather than confirming them
+            Err(Denial::Hidden) => Err(warp::reject::not_found()),
+            Err(Denial::OutsideRoot) => Err(warp::reject::custom(OutsideRoot)),
+        }
+    }
+}
+
+/// Filter that lets a request through only if the policy allows its path
+pub fn filter(policy: Arc<ServePolicy>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::path::full()
+        .and_then(move |path: FullPath| {
+            let policy = policy.clone();
+            async move { policy.check_request(path.as_str()) }
+        })
+        .untuple_one()
+}
+
+/// Rejection: the path is a symlink pointing outside the site root
+#[derive(Debug)]
+pub struct OutsideRoot;
+
+impl warp::reject::Reject for OutsideRoot {}
+
+impl OutsideRoot {
+    pub fn response(&self) -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(Body::from(
+                "403 Forbidden: symlink points outside the site root (see --follow-symlinks)\n",
+            ))
+            .unwrap_or_default()
+    }
+}
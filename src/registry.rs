@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use sysinfo::{Pid, ProcessRefreshKind, System, UpdateKind};
+
+use crate::{access_log, tls};
+
+/// A server started by hopen, as recorded in the registry
+///
+/// Records live in `<state dir>/servers/<port>.json` and are written when a
+/// server starts. They are only trusted while their process is still a
+/// running hopen; stale records are removed when read.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerRecord {
+    pub pid: u32,
+    pub port: u16,
+    pub root: PathBuf,
+    /// URL printed at startup (may include the access token)
+    pub url: String,
+    /// Log file of a background server (foreground servers log to their terminal)
+    pub log: Option<PathBuf>,
+    pub foreground: bool,
+    /// Start time (RFC 3339)
+    pub started: String,
+}
+
+/// Directory for hopen's state (server registry and logs)
+///
+/// `$XDG_STATE_HOME/hopen`, falling back to `~/.local/state/hopen`.
+pub fn state_dir() -> Result<PathBuf> {
+    let base = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| home::home_dir().map(|h| h.join(".local").join("state")))
+        .context("Failed to determine state directory")?;
+    Ok(base.join("hopen"))
+}
+
+fn servers_dir() -> Result<PathBuf> {
+    Ok(state_dir()?.join("servers"))
+}
+
+/// Directory holding the logs of background servers
+pub fn logs_dir() -> Result<PathBuf> {
+    Ok(state_dir()?.join("logs"))
+}
+
+/// File where the server on a port keeps a report (`missing` or `fixups`)
+pub fn report_path(name: &str, port: u16) -> Result<PathBuf> {
+    let dir = state_dir()?.join(name);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    Ok(dir.join(format!("server-{}.json", port)))
+}
+
+/// Log file for the background server on a port
+pub fn log_path(port: u16) -> Result<PathBuf> {
+    let dir = logs_dir()?;
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    Ok(dir.join(format!("server-{}.log", port)))
+}
+
+impl ServerRecord {
+    pub fn new(pid: u32, port: u16, root: &Path, url: &str, log: Option<PathBuf>) -> Self {
+        let started = access_log::timestamp();
+        Self {
+            pid,
+            port,
+            root: root.to_path_buf(),
+            url: url.to_string(),
+            foreground: log.is_none(),
+            log,
+            started,
+        }
+    }
+
+    /// Record the server, replacing any previous record for its port
+    ///
+    /// The file is private to the user since the URL may carry the token.
+    pub fn register(&self) -> Result<()> {
+        let dir = servers_dir()?;
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let json = serde_json::to_string_pretty(self)?;
+        tls::write_private(&dir.join(format!("{}.json", self.port)), &json)
+    }
+
+    /// Record the server; failing to do so only costs `hopen logs`, so warn
+    pub fn register_or_warn(&self) {
+        if let Err(e) = self.register() {
+            eprintln!("Warning: failed to record server in registry: {:#}", e);
+        }
+    }
+
+    /// Whether the recorded process is still a running hopen
+    fn is_alive(&self) -> bool {
+        let mut system = System::new();
+        let pid = Pid::from(self.pid as usize);
+        let refresh = ProcessRefreshKind::new().with_cmd(UpdateKind::Always);
+        if !system.refresh_process_specifics(pid, refresh) {
+            return false;
+        }
+        system.process(pid).is_some_and(|process| {
+            process
+                .cmd()
+                .first()
+                .and_then(|exe| Path::new(exe).file_name())
+                .is_some_and(|name| name == "hopen")
+        })
+    }
+}
+
+/// Records of all running servers, ordered by port
+pub fn running_servers() -> Vec<ServerRecord> {
+    let Ok(entries) = servers_dir().and_then(|dir| Ok(fs::read_dir(dir)?)) else {
+        return Vec::new();
+    };
+    let mut records: Vec<ServerRecord> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| {
+            let record = fs::read_to_string(&path)
+                .ok()
+                .and_then(|json| serde_json::from_str::<ServerRecord>(&json).ok());
+            match record {
+                Some(record) if record.is_alive() => Some(record),
+                _ => {
+                    let _ = fs::remove_file(&path);
+                    None
+                }
+            }
+        })
+        .collect();
+    records.sort_by_key(|record| record.port);
+    records
+}
+
+/// Record of the running server on a port
+pub fn find_by_port(port: u16) -> Option<ServerRecord> {
+    running_servers().into_iter().find(|record| record.port == port)
+}
+
+/// Record of the running server whose root contains `path` (the innermost one)
+pub fn find_by_path(path: &Path) -> Option<ServerRecord> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    running_servers()
+        .into_iter()
+        .filter(|record| path.starts_with(&record.root))
+        .max_by_key(|record| record.root.components().count())
+}
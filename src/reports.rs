@@ -0,0 +1,174 @@
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use warp::http::Response;
+use warp::hyper::Body;
+use warp::{Filter, Rejection};
+
+use crate::{pages, registry};
+
+/// Most entries kept in a report; requests for other keys are no longer
+/// counted
+const MAX_ENTRIES: usize = 1000;
+
+/// A report of a running server, as shown and saved
+pub trait ReportSnapshot: Serialize + DeserializeOwned + Debug + Send + 'static {
+    /// What is counted, one per key (the request path)
+    type Entry: Clone + Debug + Send + 'static;
+
+    /// Name of the `/__hopen/` page and of the state directory the report
+    /// is saved in
+    const NAME: &'static str;
+
+    /// Title of the page
+    const TITLE: &'static str;
+
+    /// The report of the server on `port`, entries most requested first
+    fn new(port: u16, root: PathBuf, entries: Vec<Self::Entry>) -> Self;
+
+    /// How often an entry was requested
+    fn count(entry: &Self::Entry) -> u64;
+
+    fn render_html(&self) -> String;
+}
+
+/// Aggregates what a running server saw by request path
+///
+/// A report is kept in memory for its `/__hopen/<name>` page and saved to
+/// the state directory after every change, so `hopen report <name>` can read
+/// it (also after the server stopped). A new server on the same port starts
+/// a fresh report.
+///
+/// The paths are chosen by clients, so a report is capped at `MAX_ENTRIES`.
+/// Saving happens off the request path, one save at a time: changes made
+/// while a save is running are written by another one right after it.
+#[derive(Debug)]
+pub struct ReportStore<R: ReportSnapshot> {
+    port: u16,
+    root: PathBuf,
+    entries: Mutex<BTreeMap<String, R::Entry>>,
+    file: Option<PathBuf>,
+    /// Changed since the last save started
+    dirty: AtomicBool,
+    /// A save is running
+    saving: AtomicBool,
+}
+
+impl<R: ReportSnapshot> ReportStore<R> {
+    pub fn new(port: u16, root: &Path) -> Self {
+        let file = registry::report_path(R::NAME, port).ok();
+        if let Some(ref file) = file {
+            let _ = fs::remove_file(file);
+        }
+        Self {
+            port,
+            root: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+            entries: Mutex::new(BTreeMap::new()),
+            file,
+            dirty: AtomicBool::new(false),
+            saving: AtomicBool::new(false),
+        }
+    }
+
+    /// Update the entry of `key` (created with `create` unless the report
+    /// is full) and save the report
+    pub fn record(
+        self: &Arc<Self>,
+        key: &str,
+        create: impl FnOnce() -> R::Entry,
+        update: impl FnOnce(&mut R::Entry),
+    ) {
+        {
+            let Ok(mut entries) = self.entries.lock() else {
+                return;
+            };
+            if entries.len() >= MAX_ENTRIES && !entries.contains_key(key) {
+                return;
+            }
+            update(entries.entry(key.to_string()).or_insert_with(create));
+        }
+        self.schedule_save();
+    }
+
+    /// Save the report in the background, unless a running save will
+    fn schedule_save(self: &Arc<Self>) {
+        self.dirty.store(true, Ordering::Release);
+        if self.saving.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let store = self.clone();
+        let save = move || loop {
+            store.dirty.store(false, Ordering::Release);
+            store.save(&store.snapshot());
+            store.saving.store(false, Ordering::Release);
+            // A change recorded during the save left it to us
+            if !store.dirty.load(Ordering::Acquire) || store.saving.swap(true, Ordering::AcqRel) {
+                break;
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(save)),
+            Err(_) => save(),
+        }
+    }
+
+    pub fn snapshot(&self) -> R {
+        let mut entries: Vec<R::Entry> = match self.entries.lock() {
+            Ok(entries) => entries.values().cloned().collect(),
+            Err(_) => Vec::new(),
+        };
+        // Most requested first; the map already ordered equal counts by path
+        entries.sort_by_key(|entry| std::cmp::Reverse(R::count(entry)));
+        R::new(self.port, self.root.clone(), entries)
+    }
+
+    fn save(&self, snapshot: &R) {
+        let Some(ref file) = self.file else {
+            return;
+        };
+        // Written aside and renamed, so readers never see half a report
+        let temp = file.with_extension("json.tmp");
+        let result = serde_json::to_string_pretty(snapshot)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| Ok(fs::write(&temp, json)?))
+            .and_then(|()| Ok(fs::rename(&temp, file)?));
+        if let Err(e) = result {
+            eprintln!("Failed to save {} report: {}", R::NAME, e);
+        }
+    }
+}
+
+/// Read the saved report of the server on a port
+pub fn load<R: ReportSnapshot>(port: u16) -> Result<R> {
+    let file = registry::report_path(R::NAME, port)?;
+    match fs::read_to_string(&file) {
+        Ok(json) => serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse {}", file.display())),
+        // Nothing recorded yet
+        Err(_) => Ok(R::new(port, PathBuf::new(), Vec::new())),
+    }
+}
+
+/// The `/__hopen/<name>` page of a report (HTML, or JSON with `?format=json`)
+pub fn filter<R: ReportSnapshot>(
+    store: Arc<ReportStore<R>>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    warp::path(pages::PREFIX)
+        .and(warp::path(R::NAME))
+        .and(warp::path::end())
+        .and(pages::query())
+        .map(move |query: String| {
+            let snapshot = store.snapshot();
+            if pages::wants_json(&query) {
+                pages::json(&snapshot)
+            } else {
+                pages::html(R::TITLE, &snapshot.render_html())
+            }
+        })
+}
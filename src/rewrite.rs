@@ -0,0 +1,172 @@
+use warp::http::{header, HeaderValue, Response, StatusCode};
+use warp::hyper::{self, Body};
+
+/// Rewrites absolute URLs of a mirrored site's origin to local paths
+///
+/// Mirrors often keep links like `https://www.example.com/css/site.css`,
+/// which would send the browser back to the live site. In HTML, CSS and
+/// JavaScript responses, such URLs become `/css/site.css`. The http and
+/// https variants, protocol-relative `//www.example.com/...` and the
+/// escaped `https:\/\/www.example.com\/...` found in JSON strings are all
+/// rewritten; files on disk are left untouched.
+#[derive(Debug, Clone, Default)]
+pub struct OriginRewriter {
+    /// Origins without scheme and trailing slash (e.g. `www.example.com`),
+    /// and what they are replaced with (empty for the site root)
+    origins: Vec<(String, String)>,
+}
+
+/// Validate a `--rewrite-origin` value and normalize it (no trailing slash)
+pub fn parse_origin(value: &str) -> Result<String, String> {
+    let lower = value.to_ascii_lowercase();
+    let rest = lower
+        .strip_prefix("https://")
+        .or_else(|| lower.strip_prefix("http://"))
+        .ok_or_else(|| "expected an http:// or https:// URL".to_string())?;
+    if rest.trim_end_matches('/').is_empty() {
+        return Err("expected a host, e.g. https://www.example.com".to_string());
+    }
+    Ok(value.trim_end_matches('/').to_string())
+}
+
+impl OriginRewriter {
+    /// Rewrite `--rewrite-origin` URLs to the site root
+    pub fn new(origins: &[String]) -> Self {
+        let mut rewriter = Self::default();
+        for (_, rest) in origins.iter().filter_map(|origin| origin.split_once("://")) {
+            rewriter.add(rest, "");
+        }
+        rewriter
+    }
+
+    /// Also rewrite URLs of `origin` (host and optional path, no scheme) to
+    /// `replacement` (a path prefix or another origin, without trailing slash)
+    pub fn add(&mut self, origin: &str, replacement: &str) {
+        self.origins.push((
+            origin.trim_end_matches('/').to_string(),
+            replacement.to_string(),
+        ));
+    }
+
+    /// Rewrite a response body if it is HTML, CSS or JavaScript
+    ///
+    /// Partial (range) responses are passed through unchanged, since their
+    /// offsets refer to the file on disk.
+    pub async fn apply(&self, response: Response<Body>) -> Response<Body> {
+        if self.origins.is_empty()
+            || response.status() != StatusCode::OK
+            || !is_rewritable(&response)
+        {
+            return response;
+        }
+
+        let (mut parts, body) = response.into_parts();
+        let bytes = match hyper::body::to_bytes(body).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("Failed to read response for rewriting: {}", e);
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::empty())
+                    .unwrap_or_default();
+            }
+        };
+        let rewritten = self.rewrite(&bytes);
+        parts.headers.insert(header::CONTENT_LENGTH, HeaderValue::from(rewritten.len()));
+        // Ranges would refer to the rewritten content, which isn't stored anywhere
+        parts.headers.remove(header::ACCEPT_RANGES);
+        Response::from_parts(parts, Body::from(rewritten))
+    }
+
+    /// Rewrite all occurrences of the origins in `content`
+    pub fn rewrite(&self, content: &[u8]) -> Vec<u8> {
+        let mut content = content.to_vec();
+        for (origin, replacement) in &self.origins {
+            content = rewrite_origin(&content, origin, replacement, "/");
+            content = rewrite_origin(&content, origin, replacement, "\\/");
+        }
+        content
+    }
+}
+
+/// Whether the response is text we rewrite (HTML, CSS or JavaScript)
+fn is_rewritable(response: &Response<Body>) -> bool {
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    matches!(
+        mime,
+        "text/html"
+            | "application/xhtml+xml"
+            | "text/css"
+            | "text/javascript"
+            | "application/javascript"
+            | "application/x-javascript"
+    )
+}
+
+/// Replace `[http[s]:]//<origin>` by `replacement`, with `slash` as the path
+/// separator (`/`, or `\/` inside JSON strings)
+fn rewrite_origin(content: &[u8], origin: &str, replacement: &str, slash: &str) -> Vec<u8> {
+    let needle = format!("{0}{0}{1}", slash, origin.replace('/', slash)).into_bytes();
+    let replacement = replacement.replace('/', slash).into_bytes();
+    let slash = slash.as_bytes();
+
+    let mut out = Vec::with_capacity(content.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while let Some(found) = find(&content[i..], &needle) {
+        let at = i + found;
+        let end = at + needle.len();
+        i = end;
+
+        // The origin must end here, not continue into a longer host name
+        // (www.example.com.evil.test) or a port
+        let next = content.get(end).copied();
+        if next.is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, b'.' | b'-' | b':' | b'_')) {
+            continue;
+        }
+
+        // Include the scheme, or require a URL boundary for protocol-relative URLs
+        let before = &content[copied..at];
+        let start = if ends_with_ignore_case(before, b"https:") {
+            at - 6
+        } else if ends_with_ignore_case(before, b"http:") {
+            at - 5
+        } else if at == 0 || is_url_boundary(content[at - 1]) {
+            at
+        } else {
+            continue;
+        };
+
+        out.extend_from_slice(&content[copied..start]);
+        out.extend_from_slice(&replacement);
+        // Keep the path as is; a bare origin becomes the root
+        if !content[end..].starts_with(slash) {
+            out.extend_from_slice(slash);
+        }
+        copied = end;
+    }
+    out.extend_from_slice(&content[copied..]);
+    out
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+}
+
+fn ends_with_ignore_case(content: &[u8], suffix: &[u8]) -> bool {
+    content.len() >= suffix.len()
+        && content[content.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+}
+
+/// Characters that may precede a protocol-relative URL
+fn is_url_boundary(c: u8) -> bool {
+    c.is_ascii_whitespace() || matches!(c, b'"' | b'\'' | b'(' | b'=' | b',' | b'`')
+}
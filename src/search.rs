@@ -0,0 +1,450 @@
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use unicode_normalization::UnicodeNormalization;
+use warp::http::Response;
+use warp::hyper::Body;
+use warp::{Filter, Rejection};
+
+use crate::policy::ServePolicy;
+use crate::{check, html, pages, sitemap};
+
+/// Most results listed for a query
+const MAX_RESULTS: usize = 50;
+
+/// Characters of context shown before and after the first match
+const SNIPPET_BEFORE: usize = 60;
+const SNIPPET_AFTER: usize = 160;
+
+/// A word in the title counts as this many occurrences in the text
+const TITLE_WEIGHT: u32 = 10;
+
+/// Searches look for changed files at most this often
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Modification time and size of a file
+type Stamp = (Option<SystemTime>, u64);
+
+/// A page as indexed
+#[derive(Debug)]
+struct IndexedPage {
+    url: String,
+    title: Option<String>,
+    text: String,
+    /// Modification time and size of the file when it was indexed
+    stamp: Stamp,
+    /// Occurrences of each word in the text
+    words: HashMap<String, u32>,
+    title_words: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    pages: HashMap<PathBuf, IndexedPage>,
+    /// Pages containing each word, sorted so prefixes can be looked up
+    words: BTreeMap<String, HashSet<PathBuf>>,
+}
+
+impl State {
+    fn insert(&mut self, path: PathBuf, page: IndexedPage) {
+        for word in page.words.keys().chain(&page.title_words) {
+            self.words.entry(word.clone()).or_default().insert(path.clone());
+        }
+        self.pages.insert(path, page);
+    }
+
+    fn remove(&mut self, path: &Path) {
+        let Some(page) = self.pages.remove(path) else {
+            return;
+        };
+        for word in page.words.keys().chain(&page.title_words) {
+            if let Some(paths) = self.words.get_mut(word) {
+                paths.remove(path);
+                if paths.is_empty() {
+                    self.words.remove(word);
+                }
+            }
+        }
+    }
+}
+
+/// In-memory full-text index of the site's HTML pages
+///
+/// Mirrors don't come with a working site search, so `/__hopen/search`
+/// provides one. The index is built when the server starts. A search more
+/// than `REFRESH_INTERVAL` after the last refresh walks the site again:
+/// pages whose modification time or size changed are indexed again and
+/// deleted ones dropped, so only the files that changed are read.
+#[derive(Debug)]
+pub struct SearchIndex {
+    root: PathBuf,
+    policy: Arc<ServePolicy>,
+    state: Mutex<State>,
+    /// When the last refresh started
+    refreshed: Mutex<Option<Instant>>,
+}
+
+/// A page matching a query
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub url: String,
+    pub title: Option<String>,
+    /// Text around the first match
+    pub snippet: String,
+    pub score: u32,
+}
+
+/// The answer to a query, best matches first
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResults {
+    pub query: String,
+    /// Pages in the index
+    pub pages: usize,
+    /// Pages matching (only the first `MAX_RESULTS` are listed)
+    pub total: usize,
+    pub results: Vec<SearchResult>,
+}
+
+impl SearchIndex {
+    pub fn new(root: &Path, policy: Arc<ServePolicy>) -> Self {
+        Self {
+            root: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+            policy,
+            state: Mutex::new(State::default()),
+            refreshed: Mutex::new(None),
+        }
+    }
+
+    /// Index new and changed pages, and forget deleted ones
+    ///
+    /// An archive being served leaves the index empty, as only a directory is
+    /// walked. The walk and the reading of changed pages don't hold the
+    /// index, so searches go on meanwhile.
+    pub fn refresh(&self) {
+        if let Ok(mut refreshed) = self.refreshed.lock() {
+            *refreshed = Some(Instant::now());
+        }
+        if !self.root.is_dir() {
+            return;
+        }
+        let stamps: HashMap<PathBuf, Stamp> = check::site_files(&self.root, &self.policy)
+            .into_iter()
+            .filter(|path| check::is_html(path))
+            .filter_map(|path| {
+                let metadata = fs::metadata(&path).ok()?;
+                Some((path, (metadata.modified().ok(), metadata.len())))
+            })
+            .collect();
+        let changed: Vec<(&PathBuf, Stamp)> = {
+            let Ok(state) = self.state.lock() else {
+                return;
+            };
+            stamps
+                .iter()
+                .filter(|(path, stamp)| {
+                    state.pages.get(*path).is_none_or(|page| page.stamp != **stamp)
+                })
+                .map(|(path, stamp)| (path, *stamp))
+                .collect()
+        };
+        let indexed: Vec<(&PathBuf, Option<IndexedPage>)> = changed
+            .into_iter()
+            .map(|(path, stamp)| (path, self.index_page(path, stamp)))
+            .collect();
+
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        for (path, page) in indexed {
+            state.remove(path);
+            if let Some(page) = page {
+                state.insert(path.clone(), page);
+            }
+        }
+        let gone: Vec<PathBuf> = state
+            .pages
+            .keys()
+            .filter(|path| !stamps.contains_key(*path))
+            .cloned()
+            .collect();
+        for path in gone {
+            state.remove(&path);
+        }
+    }
+
+    /// Refresh, unless the last refresh was less than `REFRESH_INTERVAL` ago
+    fn refresh_if_stale(&self) {
+        {
+            let Ok(mut refreshed) = self.refreshed.lock() else {
+                return;
+            };
+            if refreshed.is_some_and(|at| at.elapsed() < REFRESH_INTERVAL) {
+                return;
+            }
+            // Concurrent searches leave the walk to this one
+            *refreshed = Some(Instant::now());
+        }
+        self.refresh();
+    }
+
+    fn index_page(&self, path: &Path, stamp: Stamp) -> Option<IndexedPage> {
+        let url = sitemap::page_url(&self.root, path)?;
+        let bytes = fs::read(path).ok()?;
+        let document = html::scan(&String::from_utf8_lossy(&bytes));
+        let mut words = HashMap::new();
+        for word in words_of(&document.text) {
+            *words.entry(word).or_insert(0) += 1;
+        }
+        let title_words = document.title.as_deref().map(words_of).into_iter().flatten().collect();
+        Some(IndexedPage {
+            url,
+            title: document.title.filter(|title| !title.is_empty()),
+            // In NFC like the words, so snippets find them
+            text: document.text.nfc().collect(),
+            stamp,
+            words,
+            title_words,
+        })
+    }
+
+    /// Pages containing every word of `query` (or a word starting with it)
+    pub fn search(&self, query: &str) -> SearchResults {
+        self.refresh_if_stale();
+        let terms: Vec<String> = words_of(query).collect();
+        let Ok(state) = self.state.lock() else {
+            return SearchResults {
+                query: query.to_string(),
+                pages: 0,
+                total: 0,
+                results: Vec::new(),
+            };
+        };
+
+        let mut scores: Option<HashMap<&PathBuf, u32>> = None;
+        for term in &terms {
+            let mut term_scores: HashMap<&PathBuf, u32> = HashMap::new();
+            let matching = state
+                .words
+                .range(term.clone()..)
+                .take_while(|(word, _)| word.starts_with(term.as_str()));
+            for (word, paths) in matching {
+                for path in paths {
+                    let Some(page) = state.pages.get(path) else {
+                        continue;
+                    };
+                    let mut score = page.words.get(word).copied().unwrap_or(0);
+                    if page.title_words.contains(word) {
+                        score += TITLE_WEIGHT;
+                    }
+                    *term_scores.entry(path).or_insert(0) += score;
+                }
+            }
+            scores = Some(match scores {
+                None => term_scores,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(path, score)| term_scores.get(path).map(|s| (path, score + s)))
+                    .collect(),
+            });
+        }
+
+        let mut matches: Vec<(&IndexedPage, u32)> = scores
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(path, score)| state.pages.get(path).map(|page| (page, score)))
+            .collect();
+        matches.sort_by(|(a, a_score), (b, b_score)| {
+            b_score.cmp(a_score).then_with(|| a.url.cmp(&b.url))
+        });
+        SearchResults {
+            query: query.to_string(),
+            pages: state.pages.len(),
+            total: matches.len(),
+            results: matches
+                .into_iter()
+                .take(MAX_RESULTS)
+                .map(|(page, score)| SearchResult {
+                    url: page.url.clone(),
+                    title: page.title.clone(),
+                    snippet: snippet(&page.text, &terms),
+                    score,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The words of a text, lowercased and in NFC
+fn words_of(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.nfc().collect::<String>().to_lowercase())
+}
+
+/// The text around the first word starting with any of the terms, cut at
+/// words
+fn snippet(text: &str, terms: &[String]) -> String {
+    let mut word_start = true;
+    let at = text
+        .char_indices()
+        .find_map(|(i, c)| {
+            let found = word_start
+                && terms.iter().any(|term| match_len(&text[i..], term).is_some());
+            word_start = !c.is_alphanumeric();
+            found.then_some(i)
+        })
+        .unwrap_or(0);
+
+    let mut start = text[..at]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_BEFORE - 1)
+        .map_or(0, |(i, _)| i);
+    if start > 0 {
+        start = text[start..at].find(' ').map_or(start, |space| start + space + 1);
+    }
+    let mut end = text[at..]
+        .char_indices()
+        .nth(SNIPPET_AFTER)
+        .map_or(text.len(), |(i, _)| at + i);
+    if end < text.len() {
+        end = text[at..end].rfind(' ').map_or(end, |space| at + space);
+    }
+    format!(
+        "{}{}{}",
+        if start > 0 { "…" } else { "" },
+        &text[start..end],
+        if end < text.len() { "…" } else { "" }
+    )
+}
+
+/// A parameter of a query string, decoded (`+` is a space)
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (key == name).then(|| {
+            percent_decode_str(&value.replace('+', " "))
+                .decode_utf8_lossy()
+                .into_owned()
+        })
+    })
+}
+
+/// The `/__hopen/search?q=` page (HTML, or JSON with `&format=json`)
+pub fn filter(
+    index: Arc<SearchIndex>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    warp::path(pages::PREFIX)
+        .and(warp::path("search"))
+        .and(warp::path::end())
+        .and(pages::query())
+        .and_then(move |query: String| {
+            let index = index.clone();
+            async move {
+                let q = query_param(&query, "q").unwrap_or_default();
+                let results = tokio::task::spawn_blocking(move || index.search(&q))
+                    .await
+                    .map_err(|_| warp::reject::not_found())?;
+                Ok::<_, Rejection>(if pages::wants_json(&query) {
+                    pages::json(&results)
+                } else {
+                    pages::html("Search", &render_html(&results))
+                })
+            }
+        })
+}
+
+fn render_html(results: &SearchResults) -> String {
+    let terms: Vec<String> = words_of(&results.query).collect();
+    let mut html = format!(
+        "<form><input type=\"search\" name=\"q\" value=\"{}\" size=\"40\" autofocus> \
+         <button>Search</button></form>\n",
+        pages::escape(&results.query)
+    );
+    if terms.is_empty() {
+        html.push_str(&format!(
+            "<p class=\"muted\">{} pages indexed.</p>",
+            results.pages
+        ));
+        return html;
+    }
+    if results.results.is_empty() {
+        html.push_str(&format!(
+            "<p>No pages match <strong>{}</strong>.</p>",
+            pages::escape(&results.query)
+        ));
+        return html;
+    }
+    html.push_str(&format!(
+        "<p>{} of {} pages match. <a href=\"?q={}&amp;format=json\">JSON</a></p>\n",
+        results.total,
+        results.pages,
+        utf8_percent_encode(&results.query, NON_ALPHANUMERIC)
+    ));
+    for result in &results.results {
+        html.push_str(&format!(
+            "<p><a href=\"{0}\">{1}</a> <span class=\"muted\">{0}</span><br>{2}</p>\n",
+            pages::escape(&result.url),
+            pages::escape(result.title.as_deref().unwrap_or(&result.url)),
+            highlight(&result.snippet, &terms)
+        ));
+    }
+    html
+}
+
+/// Length in bytes of the start of `text` that lowercases to `term`
+///
+/// Compared char by char: lowercasing can change the length of a character
+/// (`ẞ` to `ß`), so offsets into a lowercased copy don't carry over.
+fn match_len(text: &str, term: &str) -> Option<usize> {
+    let mut rest = term;
+    for (i, c) in text.char_indices() {
+        if rest.is_empty() {
+            return Some(i);
+        }
+        for lower in c.to_lowercase() {
+            rest = rest.strip_prefix(lower)?;
+        }
+    }
+    rest.is_empty().then_some(text.len())
+}
+
+/// Escape a snippet for HTML, wrapping its words starting with a term in
+/// `<mark>`
+///
+/// Matches are found in the text itself, so entities aren't matched.
+fn highlight(snippet: &str, terms: &[String]) -> String {
+    let mut out = String::with_capacity(snippet.len());
+    // Start of the text not written yet
+    let mut plain = 0;
+    let mut i = 0;
+    let mut word_start = true;
+    for (at, c) in snippet.char_indices() {
+        if at < i {
+            continue;
+        }
+        let matched = terms
+            .iter()
+            .filter(|_| word_start)
+            .filter_map(|term| match_len(&snippet[at..], term))
+            .max();
+        if let Some(len) = matched {
+            out.push_str(&pages::escape(&snippet[plain..at]));
+            out.push_str("<mark>");
+            out.push_str(&pages::escape(&snippet[at..at + len]));
+            out.push_str("</mark>");
+            i = at + len;
+            plain = i;
+            word_start = false;
+            continue;
+        }
+        word_start = !c.is_alphanumeric();
+        i = at + c.len_utf8();
+    }
+    out.push_str(&pages::escape(&snippet[plain..]));
+    out
+}
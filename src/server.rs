@@ -0,0 +1,749 @@
+use anyhow::{bail, Context, Result};
+use std::convert::Infallible;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use warp::http::{header, Response, StatusCode};
+use warp::hyper::Body;
+use warp::filters::BoxedFilter;
+use warp::host::Authority;
+use warp::path::FullPath;
+use warp::{Filter, Reply};
+
+use crate::access_log::{self, AccessLog, LogFormat};
+use crate::auth::{self, Auth};
+use crate::domains::{DomainMode, Domains};
+use crate::hosts::{self, HostPolicy};
+use crate::archive::{self, Archive};
+use crate::fallback::{self, FallbackResolver};
+use crate::fixups::FixupReport;
+use crate::mirror::{self, MirrorNames};
+use crate::mhtml::{self, MhtmlArchive};
+use crate::missing::{self, MissingReport};
+use crate::policy::{self, ServePolicy};
+use crate::{pages, reports};
+use crate::rewrite::{self, OriginRewriter};
+use crate::search::{self, SearchIndex};
+use crate::sitemap;
+use crate::tls;
+use crate::warc::{self, WarcArchive};
+
+/// Default size limit of a background server's log file, in MiB
+const DEFAULT_LOG_MAX_MIB: u64 = 10;
+
+/// Options controlling how the site is served
+///
+/// These are shared by foreground and background servers. When spawning a
+/// background server they are forwarded to the child via `to_cli_args`.
+/// Those deciding what a URL resolves to are global, for `hopen check`.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct ServeArgs {
+    /// Address to listen on (IPv4 or IPv6, repeatable), e.g. 0.0.0.0 or ::.
+    /// Defaults to 127.0.0.1.
+    #[arg(long = "bind", value_name = "ADDR", conflicts_with = "lan")]
+    pub bind: Vec<IpAddr>,
+
+    /// Serve on the local network: listen on all interfaces (IPv4 and IPv6)
+    #[arg(long = "lan")]
+    pub lan: bool,
+
+    /// Serve over HTTPS with a self-signed certificate for localhost and the
+    /// bound addresses (generated once and cached)
+    #[arg(long = "https")]
+    pub https: bool,
+
+    /// TLS certificate (PEM) to use instead of the generated one (implies --https)
+    #[arg(long = "cert", value_name = "FILE", requires = "key")]
+    pub cert: Option<PathBuf>,
+
+    /// TLS private key (PEM) matching --cert
+    #[arg(long = "key", value_name = "FILE", requires = "cert")]
+    pub key: Option<PathBuf>,
+
+    /// Require HTTP basic auth with these credentials
+    #[arg(long = "auth", value_name = "USER:PASS", value_parser = auth::parse_credentials,
+          conflicts_with_all = ["token", "no_auth"])]
+    pub auth: Option<String>,
+
+    /// Require a random access token, included in the printed URL and then kept
+    /// in a cookie. This is the default when serving beyond localhost.
+    #[arg(long = "token")]
+    pub token: bool,
+
+    /// Don't require authentication, even when serving beyond localhost
+    #[arg(long = "no-auth", conflicts_with = "token")]
+    pub no_auth: bool,
+
+    /// Additional host name to accept in the Host header (repeatable).
+    /// `*.example.test` matches subdomains; `*` disables the check.
+    #[arg(long = "allow-host", value_name = "HOST")]
+    pub allow_host: Vec<String>,
+
+    /// Serve this hidden file or directory (repeatable). Dotfiles such as
+    /// .git or .env are hidden by default; .well-known is always served.
+    #[arg(long = "allow-dotfile", value_name = "NAME", global = true)]
+    pub allow_dotfile: Vec<String>,
+
+    /// Follow symlinks that point outside the site root
+    #[arg(long = "follow-symlinks", global = true)]
+    pub follow_symlinks: bool,
+
+    /// When a path isn't found, serve a file whose name only differs in
+    /// letter case (for mirrors captured on macOS or Windows)
+    #[arg(long = "ignore-case", global = true)]
+    pub ignore_case: bool,
+
+    /// When a path isn't found, try the NFC and NFD forms of its Unicode
+    /// characters (macOS often stores file names decomposed)
+    #[arg(long = "normalize-unicode", global = true)]
+    pub normalize_unicode: bool,
+
+    /// Rewrite absolute links to this origin (e.g. https://www.example.com) in
+    /// HTML, CSS and JavaScript to local paths, for browsing mirrored sites
+    /// (repeatable)
+    #[arg(long = "rewrite-origin", value_name = "URL", value_parser = rewrite::parse_origin,
+          global = true)]
+    pub rewrite_origin: Vec<String>,
+
+    /// How mirroring tools named files for URLs with a query string:
+    /// sitesucker, wget, httrack, all (the default), none, or a template such
+    /// as "{dir}{stem}_{query}{ext}" (repeatable)
+    #[arg(long = "mirror-names", value_name = "SCHEME", value_parser = mirror::validate_scheme,
+          global = true)]
+    pub mirror_names: Vec<String>,
+
+    /// Serve a mirror root whose subfolders are domains (www.example.com/,
+    /// cdn.example.com/): under their folder name (prefix) or on
+    /// <domain>.localhost (vhost). Links between the domains are rewritten.
+    #[arg(long = "domains", value_name = "MODE", value_enum, global = true)]
+    pub domains: Option<DomainMode>,
+
+    /// Access log format: printed in the foreground, written to the log file
+    /// in the background
+    #[arg(long = "log-format", value_name = "FORMAT", value_enum, default_value_t)]
+    pub log_format: LogFormat,
+
+    /// Rotate a background server's log file once it grows past this size
+    #[arg(long = "log-max-size", value_name = "MIB", default_value_t = DEFAULT_LOG_MAX_MIB)]
+    pub log_max_size: u64,
+
+    /// The token for token auth (generated by the parent, passed via the environment)
+    #[arg(skip)]
+    pub access_token: Option<String>,
+}
+
+impl ServeArgs {
+    /// Naming schemes of mirroring tools to look for (--mirror-names)
+    pub fn mirror_schemes(&self) -> Vec<mirror::NameScheme> {
+        if self.mirror_names.is_empty() {
+            return mirror::default_schemes();
+        }
+        self.mirror_names
+            .iter()
+            .filter_map(|value| mirror::parse_scheme(value).ok())
+            .flatten()
+            .collect()
+    }
+
+    /// Log size limit in bytes
+    pub fn log_max_bytes(&self) -> u64 {
+        self.log_max_size.saturating_mul(1024 * 1024)
+    }
+
+    /// Addresses to listen on, in order
+    pub fn bind_addrs(&self) -> Vec<IpAddr> {
+        if self.lan {
+            vec![
+                IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+                IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            ]
+        } else if self.bind.is_empty() {
+            vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]
+        } else {
+            self.bind.clone()
+        }
+    }
+
+    /// Whether the server is only reachable from this machine
+    pub fn is_loopback_only(&self) -> bool {
+        self.bind_addrs().iter().all(|addr| addr.is_loopback())
+    }
+
+    /// Host to use in the URL opened locally
+    ///
+    /// `localhost` works whenever we listen on loopback or on all interfaces;
+    /// otherwise we have to use the one address we are bound to.
+    fn url_host(&self) -> String {
+        let addrs = self.bind_addrs();
+        let resolves_localhost = |a: &IpAddr| {
+            a.is_unspecified()
+                || *a == IpAddr::V4(Ipv4Addr::LOCALHOST)
+                || *a == IpAddr::V6(Ipv6Addr::LOCALHOST)
+        };
+        if addrs.iter().any(resolves_localhost) {
+            return "localhost".to_string();
+        }
+        addrs.first().map(host_for).unwrap_or_else(|| "localhost".to_string())
+    }
+
+    /// Whether the server speaks HTTPS
+    pub fn is_https(&self) -> bool {
+        self.https || self.cert.is_some()
+    }
+
+    pub fn scheme(&self) -> &'static str {
+        if self.is_https() {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    /// Whether requests need the access token
+    pub fn uses_token(&self) -> bool {
+        self.auth.is_none() && (self.token || (!self.no_auth && !self.is_loopback_only()))
+    }
+
+    /// Generate the access token if token auth is in use and we don't have one yet
+    pub fn ensure_access_token(&mut self) {
+        if self.uses_token() && self.access_token.is_none() {
+            self.access_token = Some(auth::generate_token());
+        }
+    }
+
+    /// How the server running on `port` authenticates requests
+    fn auth(&self, port: u16) -> Result<Auth> {
+        if let Some(credentials) = &self.auth {
+            return Ok(Auth::basic(credentials));
+        }
+        if !self.uses_token() {
+            return Ok(Auth::Open);
+        }
+        let token = self
+            .access_token
+            .clone()
+            .context("Token auth is enabled but no access token was provided")?;
+        Ok(Auth::token(token, port, self.is_https()))
+    }
+
+    /// Add the access token to a URL (when token auth is in use)
+    fn with_token(&self, url: String) -> String {
+        match &self.access_token {
+            Some(token) if self.uses_token() => {
+                let mut url = url;
+                if url.split_once("://").is_some_and(|(_, rest)| !rest.contains('/')) {
+                    url.push('/');
+                }
+                let separator = if url.contains('?') { '&' } else { '?' };
+                format!("{}{}{}={}", url, separator, auth::TOKEN_PARAM, token)
+            }
+            _ => url,
+        }
+    }
+
+    /// URL of `path` on the server running on `port`, as opened locally
+    pub fn url(&self, port: u16, path: &str) -> String {
+        self.with_token(format!("{}://{}:{}{}", self.scheme(), self.url_host(), port, path))
+    }
+
+    /// URLs of `path` reachable from other machines (empty when loopback-only)
+    pub fn network_urls(&self, port: u16, path: &str) -> Vec<String> {
+        let mut urls: Vec<String> = Vec::new();
+        for host in self.network_hosts() {
+            let url = format!("{}://{}:{}{}", self.scheme(), host_for(&host), port, path);
+            let url = self.with_token(url);
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+        urls
+    }
+
+    /// Addresses other machines can reach us on (empty when loopback-only)
+    fn network_hosts(&self) -> Vec<IpAddr> {
+        let mut hosts: Vec<IpAddr> = Vec::new();
+        for addr in self.bind_addrs() {
+            if addr.is_unspecified() {
+                // An IPv6 wildcard is dual-stack, so it covers IPv4 too
+                let any_family = addr.is_ipv6();
+                for ip in interface_addrs() {
+                    if any_family || ip.is_ipv4() {
+                        hosts.push(ip);
+                    }
+                }
+            } else if !addr.is_loopback() {
+                hosts.push(addr);
+            }
+        }
+
+        // IPv4 first: it is what phones on the same Wi-Fi reach most reliably
+        hosts.sort_by_key(|host| host.is_ipv6());
+        hosts.dedup();
+        hosts
+    }
+
+    /// Host names requests may be addressed to
+    ///
+    /// Loopback names, the addresses we listen on and --allow-host entries.
+    /// `*.localhost` is safe to allow since it always resolves to loopback.
+    fn host_policy(&self) -> HostPolicy {
+        let mut allowed = vec![
+            "localhost".to_string(),
+            "*.localhost".to_string(),
+            Ipv4Addr::LOCALHOST.to_string(),
+            host_for(&IpAddr::V6(Ipv6Addr::LOCALHOST)),
+        ];
+        let bound = self.bind_addrs().into_iter().filter(|a| !a.is_unspecified());
+        for addr in bound.chain(self.network_hosts()) {
+            allowed.push(host_for(&addr));
+        }
+        allowed.extend(self.allow_host.iter().cloned());
+        HostPolicy::new(allowed)
+    }
+
+    /// Certificate and key files to serve HTTPS with (None for plain HTTP)
+    ///
+    /// Uses --cert/--key when given, otherwise a cached self-signed
+    /// certificate covering localhost and every address we listen on.
+    pub fn tls_files(&self) -> Result<Option<(PathBuf, PathBuf)>> {
+        if let (Some(cert), Some(key)) = (&self.cert, &self.key) {
+            return Ok(Some((cert.clone(), key.clone())));
+        }
+        if !self.https {
+            return Ok(None);
+        }
+
+        let mut hosts = vec![
+            "localhost".to_string(),
+            Ipv4Addr::LOCALHOST.to_string(),
+            Ipv6Addr::LOCALHOST.to_string(),
+        ];
+        let bound = self.bind_addrs().into_iter().filter(|a| !a.is_unspecified());
+        for addr in bound.chain(self.network_hosts()) {
+            let host = addr.to_string();
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+        tls::self_signed_certificate(&hosts).map(Some)
+    }
+
+    /// Command-line arguments that reproduce these options (for the background child)
+    ///
+    /// `--auth` credentials are left out: they go through the environment.
+    pub fn to_cli_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for addr in &self.bind {
+            args.push("--bind".to_string());
+            args.push(addr.to_string());
+        }
+        if self.lan {
+            args.push("--lan".to_string());
+        }
+        if self.https {
+            args.push("--https".to_string());
+        }
+        if let (Some(cert), Some(key)) = (&self.cert, &self.key) {
+            args.push("--cert".to_string());
+            args.push(absolute(cert).display().to_string());
+            args.push("--key".to_string());
+            args.push(absolute(key).display().to_string());
+        }
+        if self.token {
+            args.push("--token".to_string());
+        }
+        if self.no_auth {
+            args.push("--no-auth".to_string());
+        }
+        for host in &self.allow_host {
+            args.push("--allow-host".to_string());
+            args.push(host.clone());
+        }
+        for name in &self.allow_dotfile {
+            args.push("--allow-dotfile".to_string());
+            args.push(name.clone());
+        }
+        if self.follow_symlinks {
+            args.push("--follow-symlinks".to_string());
+        }
+        if self.ignore_case {
+            args.push("--ignore-case".to_string());
+        }
+        if self.normalize_unicode {
+            args.push("--normalize-unicode".to_string());
+        }
+        for origin in &self.rewrite_origin {
+            args.push("--rewrite-origin".to_string());
+            args.push(origin.clone());
+        }
+        for scheme in &self.mirror_names {
+            args.push("--mirror-names".to_string());
+            args.push(scheme.clone());
+        }
+        if let Some(mode) = self.domains {
+            args.push("--domains".to_string());
+            args.push(mode.as_arg().to_string());
+        }
+        if self.log_format != LogFormat::default() {
+            args.push("--log-format".to_string());
+            args.push(self.log_format.as_arg().to_string());
+        }
+        if self.log_max_size != DEFAULT_LOG_MAX_MIB {
+            args.push("--log-max-size".to_string());
+            args.push(self.log_max_size.to_string());
+        }
+        args
+    }
+}
+
+/// Make a user-supplied path absolute, so it survives being passed to the child
+fn absolute(path: &Path) -> PathBuf {
+    match std::env::current_dir() {
+        Ok(cwd) if path.is_relative() => cwd.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Format an address for use as a URL host (IPv6 needs brackets)
+fn host_for(addr: &IpAddr) -> String {
+    match addr {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => format!("[{}]", v6),
+    }
+}
+
+/// Non-loopback addresses of this machine's network interfaces
+///
+/// IPv6 link-local addresses are skipped since they need a zone id to be
+/// usable in a URL.
+fn interface_addrs() -> Vec<IpAddr> {
+    let Ok(interfaces) = if_addrs::get_if_addrs() else {
+        return Vec::new();
+    };
+    interfaces
+        .iter()
+        .map(|iface| iface.ip())
+        .filter(|ip| !ip.is_loopback())
+        .filter(|ip| match ip {
+            IpAddr::V6(v6) => (v6.segments()[0] & 0xffc0) != 0xfe80,
+            IpAddr::V4(_) => true,
+        })
+        .collect()
+}
+
+/// Run the warp HTTP server (used for both foreground and background modes)
+///
+/// `log_file` is the file behind stdout for background servers, which is
+/// rotated as requests are logged.
+pub async fn run_server(
+    root: &Path,
+    port: u16,
+    serve: &ServeArgs,
+    log_file: Option<&Path>,
+) -> Result<()> {
+    // Set up Ctrl+C handler for graceful shutdown
+    let should_exit = Arc::new(AtomicBool::new(false));
+    let s_exit = should_exit.clone();
+
+    ctrlc::set_handler(move || {
+        s_exit.store(true, Ordering::SeqCst);
+        std::process::exit(0);
+    })
+    .ok(); // Ignore error if handler already set
+
+    // Serve files using warp, checking the Host header first, then
+    // authentication (when enabled) and finally whether the path may be served.
+    // hopen's own pages (reports, sitemap, search) live under /__hopen/; the
+    // search index is built in the background. Every response, including
+    // rejections, goes through the access log and the missing-resource
+    // report. Paths found only by fixing their case or normalization are
+    // reported too.
+    let host_policy = Arc::new(serve.host_policy());
+    let auth = Arc::new(serve.auth(port)?);
+    let missing_report = Arc::new(MissingReport::new(port, root));
+    let fixup_report = Arc::new(FixupReport::new(port, root));
+    let page_policy = Arc::new(ServePolicy::new(
+        root,
+        &serve.allow_dotfile,
+        serve.follow_symlinks,
+    ));
+    let search_index = Arc::new(SearchIndex::new(root, page_policy.clone()));
+    let startup_index = search_index.clone();
+    tokio::task::spawn_blocking(move || startup_index.refresh());
+    let pages = reports::filter(missing_report.clone())
+        .or(reports::filter(fixup_report.clone()))
+        .unify()
+        .or(sitemap::filter(root, page_policy, serve.scheme()))
+        .unify()
+        .or(search::filter(search_index))
+        .unify();
+    let fallback = (serve.ignore_case || serve.normalize_unicode).then(|| {
+        Arc::new(FallbackResolver::new(
+            serve.ignore_case,
+            serve.normalize_unicode,
+            Some(fixup_report),
+        ))
+    });
+    let domains = serve
+        .domains
+        .map(|mode| Arc::new(Domains::new(mode, root, serve.scheme(), port)));
+    let mut rewriter = OriginRewriter::new(&serve.rewrite_origin);
+    if let Some(ref domains) = domains {
+        domains.add_rewrites(&mut rewriter);
+    }
+    let warc = if warc::is_warc(root) {
+        let warc = Arc::new(WarcArchive::open(root)?);
+        warc.add_rewrites(&mut rewriter);
+        Some(warc)
+    } else {
+        None
+    };
+    let rewriter = Arc::new(rewriter);
+    let site = if let Some(warc) = warc {
+        warc::filter(warc)
+            .then(move |response: Response<Body>| {
+                let rewriter = rewriter.clone();
+                async move { rewriter.apply(response).await }
+            })
+            .boxed()
+    } else if mhtml::is_mhtml(root) {
+        let mhtml = Arc::new(MhtmlArchive::open(root)?);
+        mhtml::filter(mhtml)
+            .then(move |response: Response<Body>| {
+                let rewriter = rewriter.clone();
+                async move { rewriter.apply(response).await }
+            })
+            .boxed()
+    } else if archive::is_archive(root) {
+        let archive = Arc::new(Archive::open(root)?);
+        archive_site(root, archive, serve, rewriter)
+    } else {
+        match domains {
+            Some(domains) => domain_site(root, serve, rewriter, fallback, domains),
+            None => static_files(root, serve, rewriter, fallback),
+        }
+    };
+    let route = hosts::filter(host_policy)
+        .and(auth::filter(auth))
+        .and(pages.or(site).unify())
+        .recover(handle_rejection)
+        .unify();
+    let rotation = log_file.map(|path| (path.to_path_buf(), serve.log_max_bytes()));
+    let access_log = AccessLog::new(serve.log_format, rotation);
+    let route = access_log::request_info()
+        .and(route)
+        .map(move |info, reply: warp::reply::Response| {
+            access_log.log(&info, &reply);
+            missing::record(&missing_report, &info, &reply);
+            reply
+        });
+    let tls = serve.tls_files()?;
+
+    // With --lan we listen on the IPv6 wildcard, which is dual-stack on most
+    // systems. The IPv4 wildcard is then only needed where IPv6 sockets are
+    // v6-only (or IPv6 is unavailable), so failing to bind it is fine as long
+    // as one of the two succeeded.
+    let mut servers = Vec::new();
+    for addr in serve.bind_addrs() {
+        let socket_addr = SocketAddr::new(addr, port);
+        let bound = match &tls {
+            Some((cert, key)) => warp::serve(route.clone())
+                .tls()
+                .cert_path(cert)
+                .key_path(key)
+                .try_bind_with_graceful_shutdown(socket_addr, std::future::pending())
+                .map(|(_, server)| tokio::spawn(server)),
+            None => warp::serve(route.clone())
+                .try_bind_ephemeral(socket_addr)
+                .map(|(_, server)| tokio::spawn(server)),
+        };
+        match bound {
+            Ok(server) => servers.push(server),
+            Err(_) if serve.lan && !servers.is_empty() => {}
+            Err(_) if serve.lan && addr.is_ipv6() => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to bind {}", socket_addr));
+            }
+        }
+    }
+    if servers.is_empty() {
+        bail!("Failed to bind any address on port {}", port);
+    }
+
+    for server in servers {
+        server.await?;
+    }
+
+    Ok(())
+}
+
+/// Serve the files under `dir`, as far as the serve policy allows
+///
+/// URLs with a query string are first looked up under the names mirroring
+/// tools save them as; everything else goes to `warp::fs::dir`. Paths it
+/// can't find go to the `fallback` resolver (--ignore-case,
+/// --normalize-unicode), if any.
+fn static_files(
+    dir: &Path,
+    serve: &ServeArgs,
+    rewriter: Arc<OriginRewriter>,
+    fallback: Option<Arc<FallbackResolver>>,
+) -> BoxedFilter<(Response<Body>,)> {
+    let serve_policy = Arc::new(ServePolicy::new(
+        dir,
+        &serve.allow_dotfile,
+        serve.follow_symlinks,
+    ));
+    let mirror_names = Arc::new(MirrorNames::new(dir, &serve.mirror_schemes()));
+    let mirrored = mirror::filter(dir, mirror_names, serve_policy.clone());
+    let files = warp::fs::dir(dir.to_path_buf()).map(Reply::into_response);
+    let mut found = mirrored.or(files).unify().boxed();
+    if let Some(resolver) = fallback {
+        found = found
+            .or(fallback::filter(dir, resolver, serve_policy.clone()))
+            .unify()
+            .boxed();
+    }
+    policy::filter(serve_policy)
+        .and(found)
+        .then(move |response: Response<Body>| {
+            let rewriter = rewriter.clone();
+            async move { rewriter.apply(response).await }
+        })
+        .boxed()
+}
+
+/// Serve the files of an archive (`-r site.zip`), as far as the serve policy
+/// allows
+fn archive_site(
+    path: &Path,
+    archive: Arc<Archive>,
+    serve: &ServeArgs,
+    rewriter: Arc<OriginRewriter>,
+) -> BoxedFilter<(Response<Body>,)> {
+    // Only the hidden-file rules apply: archive entries aren't symlinks
+    let serve_policy = Arc::new(ServePolicy::new(path, &serve.allow_dotfile, true));
+    policy::filter(serve_policy)
+        .and(archive::filter(archive))
+        .then(move |response: Response<Body>| {
+            let rewriter = rewriter.clone();
+            async move { rewriter.apply(response).await }
+        })
+        .boxed()
+}
+
+/// Serve a multi-domain mirror (--domains)
+///
+/// With vhosts, `<domain>.localhost` is served from the domain's folder and
+/// any other host from the mirror root. With path prefixes the root is
+/// served as usual, and root-relative links from a domain's pages are
+/// redirected into its folder. Either way `/` lists the domains when the
+/// root has no index page.
+fn domain_site(
+    root: &Path,
+    serve: &ServeArgs,
+    rewriter: Arc<OriginRewriter>,
+    fallback: Option<Arc<FallbackResolver>>,
+    domains: Arc<Domains>,
+) -> BoxedFilter<(Response<Body>,)> {
+    let mut site = static_files(root, serve, rewriter.clone(), fallback.clone());
+
+    if domains.mode() == DomainMode::Vhost {
+        let vhost_domains = domains.clone();
+        site = warp::host::optional()
+            .and_then(move |authority: Option<Authority>| {
+                let is_vhost = authority.is_some_and(|a| vhost_domains.for_host(a.host()).is_some());
+                async move {
+                    if is_vhost {
+                        Err(warp::reject::not_found())
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+            .untuple_one()
+            .and(site)
+            .boxed();
+
+        for name in domains.names() {
+            let host = format!("{}.localhost", name);
+            let files = static_files(&domains.dir(name), serve, rewriter.clone(), fallback.clone());
+            site = warp::host::optional()
+                .and_then(move |authority: Option<Authority>| {
+                    let matches = authority.is_some_and(|a| {
+                        a.host().trim_end_matches('.').eq_ignore_ascii_case(&host)
+                    });
+                    async move {
+                        if matches {
+                            Ok(())
+                        } else {
+                            Err(warp::reject::not_found())
+                        }
+                    }
+                })
+                .untuple_one()
+                .and(files)
+                .or(site)
+                .unify()
+                .boxed();
+        }
+    }
+
+    let redirect_domains = domains.clone();
+    let redirect_policy = Arc::new(ServePolicy::new(
+        root,
+        &serve.allow_dotfile,
+        serve.follow_symlinks,
+    ));
+    let referer_redirect = warp::path::full()
+        .and(pages::query())
+        .and(warp::header::optional::<String>("referer"))
+        .and_then(move |path: FullPath, query: String, referer: Option<String>| {
+            let redirect = redirect_domains.redirect_from_referer(
+                path.as_str(),
+                &query,
+                referer.as_deref(),
+                &redirect_policy,
+            );
+            async move { redirect.ok_or_else(warp::reject::not_found) }
+        });
+    let index = warp::get()
+        .and(warp::path::end())
+        .map(move || domains.index_page());
+
+    site.or(referer_redirect)
+        .unify()
+        .or(index)
+        .unify()
+        .boxed()
+}
+
+/// Turn rejections into responses, so that every request can be logged
+async fn handle_rejection(err: warp::Rejection) -> Result<warp::reply::Response, Infallible> {
+    if let Some(misdirected) = err.find::<hosts::MisdirectedHost>() {
+        return Ok(misdirected.response());
+    }
+    if let Some(unauthorized) = err.find::<auth::Unauthorized>() {
+        return Ok(unauthorized.response());
+    }
+    if let Some(accepted) = err.find::<auth::TokenAccepted>() {
+        return Ok(accepted.response());
+    }
+    if let Some(outside) = err.find::<policy::OutsideRoot>() {
+        return Ok(outside.response());
+    }
+    let (status, body) = if err.is_not_found() {
+        (StatusCode::NOT_FOUND, "404 Not Found\n")
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        (StatusCode::METHOD_NOT_ALLOWED, "405 Method Not Allowed\n")
+    } else {
+        (StatusCode::BAD_REQUEST, "400 Bad Request\n")
+    };
+    Ok(Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Body::from(body))
+        .unwrap_or_default())
+}
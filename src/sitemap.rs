@@ -0,0 +1,300 @@
+use anyhow::{bail, Context, Result};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use time::OffsetDateTime;
+use warp::host::Authority;
+use warp::http::{header, Response};
+use warp::hyper::Body;
+use warp::{Filter, Rejection};
+
+use crate::policy::ServePolicy;
+use crate::{access_log, check, html, pages};
+
+/// Characters escaped in page URLs
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?');
+
+/// Output formats of `hopen sitemap`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// sitemap.xml, as read by search engines
+    Xml,
+    /// The page inventory as JSON
+    Json,
+    /// The page inventory as CSV
+    Csv,
+}
+
+impl Format {
+    /// The format a file name calls for (`.xml`, `.json` or `.csv`)
+    fn of(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "xml" => Some(Format::Xml),
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Arguments of `hopen sitemap`
+#[derive(clap::Args, Debug)]
+pub struct SitemapArgs {
+    /// Site root to list. Defaults to HOPEN_SITE_HOME, then the current
+    /// directory.
+    #[arg(value_name = "ROOT")]
+    pub root: Option<PathBuf>,
+
+    /// Format printed on stdout
+    #[arg(long = "format", value_enum, default_value = "xml")]
+    pub format: Format,
+
+    /// Write to this file instead, in the format of its extension (.xml,
+    /// .json or .csv; repeatable)
+    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    pub output: Vec<PathBuf>,
+
+    /// Origin the URLs of sitemap.xml start with. Defaults to the first
+    /// --rewrite-origin, then http://localhost:8000.
+    #[arg(long = "base-url", value_name = "URL", value_parser = crate::rewrite::parse_origin)]
+    pub base_url: Option<String>,
+}
+
+/// A page of the site
+#[derive(Debug, Clone, Serialize)]
+pub struct Page {
+    /// URL path (a directory's index page is listed as the directory)
+    pub url: String,
+    pub title: Option<String>,
+    /// Size in bytes
+    pub size: u64,
+    pub last_modified: Option<String>,
+    /// Number of `<a href>` links, same-page anchors excepted
+    pub links: usize,
+}
+
+/// The pages of a site, by URL
+#[derive(Debug, Clone, Serialize)]
+pub struct Inventory {
+    pub root: PathBuf,
+    pub pages: Vec<Page>,
+}
+
+/// List the HTML pages under `root` that may be served
+///
+/// Only a directory root is walked; an archive being served lists no pages.
+pub fn inventory(root: &Path, policy: &ServePolicy) -> Inventory {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let mut pages = Vec::new();
+    if root.is_dir() {
+        for path in check::site_files(&root, policy) {
+            if let Some(page) = page(&root, &path) {
+                pages.push(page);
+            }
+        }
+    }
+    pages.sort_by(|a, b| a.url.cmp(&b.url));
+    Inventory { root, pages }
+}
+
+fn page(root: &Path, path: &Path) -> Option<Page> {
+    if !check::is_html(path) {
+        return None;
+    }
+    let metadata = fs::metadata(path).ok()?;
+    let text = String::from_utf8_lossy(&fs::read(path).ok()?).into_owned();
+    let document = html::scan(&text);
+    let links = document
+        .references
+        .iter()
+        .filter(|r| r.is_link && !r.url.trim().is_empty() && !r.url.trim().starts_with('#'))
+        .count();
+
+    Some(Page {
+        url: page_url(root, path)?,
+        title: document.title.filter(|t| !t.is_empty()),
+        size: metadata.len(),
+        last_modified: metadata
+            .modified()
+            .ok()
+            .map(|modified| {
+                let modified = OffsetDateTime::from(modified);
+                access_log::rfc3339(modified.replace_nanosecond(0).unwrap_or(modified))
+            }),
+        links,
+    })
+}
+
+/// URL path of a page under `root` (a directory's index page is listed as
+/// the directory)
+pub fn page_url(root: &Path, path: &Path) -> Option<String> {
+    let mut url: String = path
+        .strip_prefix(root)
+        .ok()?
+        .iter()
+        .map(|s| format!("/{}", utf8_percent_encode(&s.to_string_lossy(), SEGMENT)))
+        .collect();
+    if let Some(dir) = url.strip_suffix("/index.html") {
+        url = format!("{}/", dir);
+    }
+    Some(url)
+}
+
+impl Inventory {
+    /// The inventory in `format`; `base_url` is the origin of sitemap.xml's URLs
+    pub fn render(&self, format: Format, base_url: &str) -> String {
+        match format {
+            Format::Xml => self.to_xml(base_url),
+            Format::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+            Format::Csv => self.to_csv(),
+        }
+    }
+
+    fn to_xml(&self, base_url: &str) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+        );
+        for page in &self.pages {
+            let url = format!("{}{}", base_url.trim_end_matches('/'), page.url);
+            xml.push_str(&format!("  <url>\n    <loc>{}</loc>\n", pages::escape(&url)));
+            if let Some(ref modified) = page.last_modified {
+                xml.push_str(&format!("    <lastmod>{}</lastmod>\n", modified));
+            }
+            xml.push_str("  </url>\n");
+        }
+        xml.push_str("</urlset>\n");
+        xml
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = String::from("url,title,size,last_modified,links\n");
+        for page in &self.pages {
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                csv_field(&page.url),
+                csv_field(page.title.as_deref().unwrap_or("")),
+                page.size,
+                page.last_modified.as_deref().unwrap_or(""),
+                page.links
+            ));
+        }
+        csv
+    }
+}
+
+/// Quote a CSV field when needed
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Write the inventory to each output file, in the format of its extension
+pub fn write(inventory: &Inventory, outputs: &[PathBuf], base_url: &str) -> Result<()> {
+    let mut formats = Vec::new();
+    for output in outputs {
+        let Some(format) = Format::of(output) else {
+            bail!(
+                "Can't tell the format of {}: use a .xml, .json or .csv file",
+                output.display()
+            );
+        };
+        formats.push(format);
+    }
+    for (output, format) in outputs.iter().zip(formats) {
+        fs::write(output, inventory.render(format, base_url))
+            .with_context(|| format!("Failed to write {}", output.display()))?;
+    }
+    Ok(())
+}
+
+/// The `/__hopen/sitemap` page (HTML, or `?format=json`, `csv` or `xml`)
+///
+/// Listed afresh on every request; sitemap.xml URLs use the request's host.
+pub fn filter(
+    root: &Path,
+    policy: Arc<ServePolicy>,
+    scheme: &'static str,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let root = Arc::new(root.to_path_buf());
+    warp::path(pages::PREFIX)
+        .and(warp::path("sitemap"))
+        .and(warp::path::end())
+        .and(pages::query())
+        .and(warp::host::optional())
+        .and_then(move |query: String, authority: Option<Authority>| {
+            let root = root.clone();
+            let policy = policy.clone();
+            async move {
+                let inventory = tokio::task::spawn_blocking(move || inventory(&root, &policy))
+                    .await
+                    .map_err(|_| warp::reject::not_found())?;
+                let base_url = match authority {
+                    Some(authority) => format!("{}://{}", scheme, authority),
+                    None => String::new(),
+                };
+                let format = query.split('&').find_map(|pair| match pair {
+                    "format=json" => Some(Format::Json),
+                    "format=csv" => Some(Format::Csv),
+                    "format=xml" => Some(Format::Xml),
+                    _ => None,
+                });
+                Ok::<_, Rejection>(match format {
+                    Some(Format::Json) => pages::json(&inventory),
+                    Some(format) => {
+                        let content_type = match format {
+                            Format::Xml => "application/xml; charset=utf-8",
+                            _ => "text/csv; charset=utf-8",
+                        };
+                        Response::builder()
+                            .header(header::CONTENT_TYPE, content_type)
+                            .header(header::CACHE_CONTROL, "no-store")
+                            .body(Body::from(inventory.render(format, &base_url)))
+                            .unwrap_or_default()
+                    }
+                    None => pages::html("Sitemap", &render_html(&inventory)),
+                })
+            }
+        })
+}
+
+fn render_html(inventory: &Inventory) -> String {
+    if inventory.pages.is_empty() {
+        return "<p>No HTML pages to list.</p>".to_string();
+    }
+    let mut html = format!(
+        "<p>{} pages under <code>{}</code>. \
+         <a href=\"?format=xml\">sitemap.xml</a> \
+         <a href=\"?format=json\">JSON</a> <a href=\"?format=csv\">CSV</a></p>\n\
+         <table>\n<tr><th>Page</th><th>Title</th><th>Size</th>\
+         <th>Last modified</th><th>Links</th></tr>\n",
+        inventory.pages.len(),
+        pages::escape(&inventory.root.display().to_string())
+    );
+    for page in &inventory.pages {
+        html.push_str(&format!(
+            "<tr><td><a href=\"{0}\">{0}</a></td><td>{1}</td><td class=\"count\">{2}</td>\
+             <td>{3}</td><td class=\"count\">{4}</td></tr>\n",
+            pages::escape(&page.url),
+            pages::escape(page.title.as_deref().unwrap_or("")),
+            page.size,
+            page.last_modified.as_deref().unwrap_or(""),
+            page.links
+        ));
+    }
+    html.push_str("</table>");
+    html
+}
@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory where the generated certificate is cached
+///
+/// `$XDG_CACHE_HOME/hopen/tls`, falling back to `~/.cache/hopen/tls`.
+fn cache_dir() -> Result<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| home::home_dir().map(|h| h.join(".cache")))
+        .context("Failed to determine cache directory")?;
+    Ok(base.join("hopen").join("tls"))
+}
+
+/// Get a self-signed certificate and key (PEM files) valid for all `hosts`
+///
+/// The certificate is cached and reused as long as it covers every host; when
+/// a new host shows up (e.g. a different LAN address) a fresh certificate is
+/// generated for the union of the old and new hosts, so browsers that already
+/// trust the old one only need to accept it once more.
+pub fn self_signed_certificate(hosts: &[String]) -> Result<(PathBuf, PathBuf)> {
+    let dir = cache_dir()?;
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    let hosts_path = dir.join("hosts");
+
+    let mut cached_hosts: Vec<String> = fs::read_to_string(&hosts_path)
+        .map(|s| s.lines().map(str::to_string).collect())
+        .unwrap_or_default();
+    let covered = hosts.iter().all(|h| cached_hosts.contains(h));
+    if covered && cert_path.exists() && key_path.exists() {
+        return Ok((cert_path, key_path));
+    }
+
+    for host in hosts {
+        if !cached_hosts.contains(host) {
+            cached_hosts.push(host.clone());
+        }
+    }
+
+    let mut params = CertificateParams::new(cached_hosts.clone())
+        .context("Invalid host name for certificate")?;
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, "hopen local development");
+    params.distinguished_name = name;
+
+    let key_pair = KeyPair::generate().context("Failed to generate TLS key")?;
+    let cert = params
+        .self_signed(&key_pair)
+        .context("Failed to generate TLS certificate")?;
+
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    fs::write(&cert_path, cert.pem())?;
+    write_private(&key_path, &key_pair.serialize_pem())?;
+    fs::write(&hosts_path, cached_hosts.join("\n"))?;
+
+    Ok((cert_path, key_path))
+}
+
+/// Write a file readable only by the current user
+pub fn write_private(path: &Path, contents: &str) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        file.write_all(contents.as_bytes())?;
+    }
+    #[cfg(not(unix))]
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
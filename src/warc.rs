@@ -0,0 +1,430 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use warp::http::{header, HeaderName, HeaderValue, Response, StatusCode};
+use warp::hyper::Body;
+use warp::path::FullPath;
+use warp::{Filter, Rejection};
+
+use crate::rewrite::OriginRewriter;
+use crate::{archive, pages};
+
+/// Archived headers that would misbehave on a local server: hop-by-hop
+/// headers, and ones pinning the live host to HTTPS
+const DROPPED_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "transfer-encoding",
+    "content-length",
+    "strict-transport-security",
+    "alt-svc",
+    "public-key-pins",
+];
+
+/// Whether `path` is a WARC file (`.warc` or `.warc.gz`)
+pub fn is_warc(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let name = name.to_ascii_lowercase();
+    path.is_file() && (name.ends_with(".warc") || name.ends_with(".warc.gz"))
+}
+
+/// An archived HTTP response: where its block is in the WARC file
+#[derive(Debug, Clone, Copy)]
+struct Record {
+    offset: u64,
+    len: u64,
+    status: u16,
+}
+
+impl Record {
+    fn is_redirect(&self) -> bool {
+        (300..400).contains(&self.status)
+    }
+}
+
+/// A crawl served from a WARC file
+///
+/// Response records are indexed by target URI when the server starts, and
+/// replayed with their original status and headers on request. The host of
+/// the first response is served at the root; other hosts under
+/// `/<host>/...`, with links to them rewritten accordingly. Like tar.gz, a
+/// .warc.gz is decompressed once into a temporary file.
+pub struct WarcArchive {
+    /// Records by host and path with query (`www.example.com/page?id=3`)
+    records: HashMap<String, Record>,
+    /// Hosts in order of their first response
+    hosts: Vec<String>,
+    /// Captured pages, for the index shown when the root page wasn't captured
+    pages: Vec<String>,
+    file: Mutex<File>,
+}
+
+impl WarcArchive {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let is_gzip = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.to_ascii_lowercase().ends_with(".gz"));
+        let file = if is_gzip { archive::decompress(file)? } else { file };
+        Self::index(file).with_context(|| format!("Failed to read WARC file {}", path.display()))
+    }
+
+    fn index(file: File) -> Result<Self> {
+        let mut reader = BufReader::new(file);
+        let mut records = HashMap::new();
+        let mut hosts: Vec<String> = Vec::new();
+        let mut pages = Vec::new();
+        let mut line = String::new();
+        loop {
+            // Records are separated by blank lines
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            if !line.starts_with("WARC/") {
+                bail!("expected a WARC record, found {:?}", line.trim());
+            }
+            let headers = read_headers(&mut reader)?;
+            let len: u64 = headers
+                .get("content-length")
+                .and_then(|v| v.parse().ok())
+                .context("WARC record without Content-Length")?;
+            let offset = reader.stream_position()?;
+            // The status line, to prefer real pages over redirects between
+            // http:// and https:// (both map to the same local URL)
+            let mut status_line = String::new();
+            (&mut reader).take(len).read_line(&mut status_line)?;
+            reader.seek_relative(len as i64 - status_line.len() as i64)?;
+            let status = status_line
+                .split_whitespace()
+                .nth(1)
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0);
+
+            let is_response = headers.get("warc-type").map(String::as_str) == Some("response");
+            let target = headers.get("warc-target-uri").and_then(|uri| uri_key(uri));
+            let (true, Some((host, key))) = (is_response, target) else {
+                continue;
+            };
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+            // The first capture of a URL wins, unless it was a redirect
+            let record = Record { offset, len, status };
+            match records.get(&key) {
+                None => {
+                    pages.push(key.clone());
+                    records.insert(key, record);
+                }
+                Some(existing) if existing.is_redirect() && !record.is_redirect() => {
+                    records.insert(key, record);
+                }
+                Some(_) => {}
+            }
+        }
+        if records.is_empty() {
+            bail!("no archived responses found");
+        }
+        let mut file = reader.into_inner();
+        file.seek(SeekFrom::Start(0))?;
+        Ok(Self {
+            records,
+            hosts,
+            pages,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Host served at the root
+    fn primary_host(&self) -> &str {
+        &self.hosts[0]
+    }
+
+    /// Local path of an archived URL's host and path
+    fn local_path(&self, host: &str, path: &str) -> String {
+        if host == self.primary_host() {
+            path.to_string()
+        } else {
+            format!("/{}{}", host, path)
+        }
+    }
+
+    /// Point links to the archived hosts at their local paths
+    pub fn add_rewrites(&self, rewriter: &mut OriginRewriter) {
+        for host in &self.hosts {
+            rewriter.add(host, &self.local_path(host, ""));
+        }
+    }
+
+    /// The record for a request path (with query): on the primary host, on
+    /// the host named by the first segment, or else on any host
+    fn find(&self, path: &str) -> Option<Record> {
+        if let Some(record) = self.records.get(&format!("{}{}", self.primary_host(), path)) {
+            return Some(*record);
+        }
+        let rest = path.trim_start_matches('/');
+        let (host, host_path) = rest.split_once('/').unwrap_or((rest, ""));
+        if self.hosts.iter().any(|h| h == host) {
+            if let Some(record) = self.records.get(&format!("{}/{}", host, host_path)) {
+                return Some(*record);
+            }
+        }
+        self.hosts
+            .iter()
+            .find_map(|host| self.records.get(&format!("{}{}", host, path)))
+            .copied()
+    }
+
+    fn read(&self, record: Record) -> io::Result<Vec<u8>> {
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| io::Error::other("WARC reader poisoned"))?;
+        file.seek(SeekFrom::Start(record.offset))?;
+        // Not preallocated: `record.len` comes from the file, which may lie
+        let mut block = Vec::new();
+        (&mut *file).take(record.len).read_to_end(&mut block)?;
+        Ok(block)
+    }
+
+    /// Replay the archived response for a request path
+    fn respond(&self, path: &str) -> Option<Response<Body>> {
+        let Some(record) = self.find(path) else {
+            return (path == "/").then(|| self.index_page());
+        };
+        let block = match self.read(record) {
+            Ok(block) => block,
+            Err(e) => {
+                eprintln!("Failed to read WARC record for {}: {}", path, e);
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::empty())
+                    .ok();
+            }
+        };
+        Some(self.replay(&block).unwrap_or_else(|| {
+            Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                .body(Body::from("502 Bad Gateway: the archived response can't be parsed\n"))
+                .unwrap_or_default()
+        }))
+    }
+
+    /// Turn an archived HTTP response (status line, headers, body) into ours
+    fn replay(&self, block: &[u8]) -> Option<Response<Body>> {
+        let (head, body) = split_head(block)?;
+        let head = String::from_utf8_lossy(head);
+        let mut lines = head.lines();
+        let status: u16 = lines.next()?.split_whitespace().nth(1)?.parse().ok()?;
+        let mut response = Response::builder().status(StatusCode::from_u16(status).ok()?);
+        let mut chunked = false;
+        let mut encoding = None;
+        for line in lines {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (name, value) = (name.trim().to_ascii_lowercase(), value.trim());
+            if name == "transfer-encoding" && value.to_ascii_lowercase().contains("chunked") {
+                chunked = true;
+            }
+            if name == "content-encoding" {
+                // Added back below unless the body can be decoded
+                encoding = Some(value.to_string());
+                continue;
+            }
+            if DROPPED_HEADERS.contains(&name.as_str()) {
+                continue;
+            }
+            let value = if name == "location" {
+                self.local_location(value)
+            } else {
+                value.to_string()
+            };
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                response = response.header(name, value);
+            }
+        }
+        let mut body = if chunked { dechunk(body) } else { body.to_vec() };
+        // Crawls often store compressed bodies; decode them so links can be
+        // rewritten
+        if let Some(encoding) = encoding {
+            match decode(&encoding, &body) {
+                Some(decoded) => body = decoded,
+                None => response = response.header(header::CONTENT_ENCODING, encoding),
+            }
+        }
+        response
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body))
+            .ok()
+    }
+
+    /// Redirects to an archived host stay on the local server
+    fn local_location(&self, location: &str) -> String {
+        match uri_key(location) {
+            Some((host, key)) if self.hosts.contains(&host) => {
+                self.local_path(&host, &key[host.len()..])
+            }
+            _ => location.to_string(),
+        }
+    }
+
+    /// List the captured pages (when the root page wasn't captured)
+    fn index_page(&self) -> Response<Body> {
+        let items: Vec<String> = self
+            .pages
+            .iter()
+            .map(|key| {
+                let (host, path) = key.split_at(key.find('/').unwrap_or(key.len()));
+                let local = self.local_path(host, path);
+                format!(
+                    "<li><a href=\"{}\">{}</a></li>",
+                    pages::escape(&local),
+                    pages::escape(key)
+                )
+            })
+            .collect();
+        pages::html(
+            "Archived pages",
+            &format!("<ul>\n{}\n</ul>", items.join("\n")),
+        )
+    }
+}
+
+/// Filter replaying the archived responses of a WARC file
+///
+/// Rejects with a 404 for URLs that weren't captured.
+pub fn filter(
+    warc: Arc<WarcArchive>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    warp::path::full()
+        .and(pages::query())
+        .and_then(move |path: FullPath, query: String| {
+            let warc = warc.clone();
+            async move {
+                let mut path = path.as_str().to_string();
+                if !query.is_empty() {
+                    path.push('?');
+                    path.push_str(&query);
+                }
+                tokio::task::spawn_blocking(move || warc.respond(&path))
+                    .await
+                    .ok()
+                    .flatten()
+                    .ok_or_else(warp::reject::not_found)
+            }
+        })
+}
+
+/// Read `Name: value` lines up to a blank line, names lowercased
+fn read_headers(reader: &mut impl BufRead) -> Result<HashMap<String, String>> {
+    let mut headers = HashMap::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            return Ok(headers);
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+}
+
+/// Host and index key (`host/path?query`) of an absolute http(s) URL
+///
+/// The host is lowercased without default port, the fragment dropped and a
+/// missing path taken as `/`.
+fn uri_key(uri: &str) -> Option<(String, String)> {
+    let uri = uri.trim().trim_start_matches('<').trim_end_matches('>');
+    let lower = uri.get(..8)?.to_ascii_lowercase();
+    let rest = if lower.starts_with("https://") {
+        &uri[8..]
+    } else if lower.starts_with("http://") {
+        &uri[7..]
+    } else {
+        return None;
+    };
+    let rest = rest.split('#').next().unwrap_or(rest);
+    let split = rest.find(['/', '?']).unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(split);
+    let host = authority.rsplit('@').next()?.to_ascii_lowercase();
+    let host = host
+        .strip_suffix(":80")
+        .or_else(|| host.strip_suffix(":443"))
+        .unwrap_or(&host)
+        .to_string();
+    if host.is_empty() {
+        return None;
+    }
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+    Some((host.clone(), format!("{}{}", host, path)))
+}
+
+/// Split an HTTP message into head and body
+fn split_head(block: &[u8]) -> Option<(&[u8], &[u8])> {
+    if let Some(at) = block.windows(4).position(|w| w == b"\r\n\r\n") {
+        return Some((&block[..at], &block[at + 4..]));
+    }
+    let at = block.windows(2).position(|w| w == b"\n\n")?;
+    Some((&block[..at], &block[at + 2..]))
+}
+
+/// Decode a gzip or deflate `Content-Encoding` (`None` for other encodings
+/// and bodies that fail to decode)
+fn decode(encoding: &str, body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    match encoding.trim().to_ascii_lowercase().as_str() {
+        "identity" => decoded.extend_from_slice(body),
+        "gzip" | "x-gzip" => {
+            MultiGzDecoder::new(body).read_to_end(&mut decoded).ok()?;
+        }
+        // Meant to be zlib-wrapped, but some servers send raw deflate
+        "deflate" => {
+            if ZlibDecoder::new(body).read_to_end(&mut decoded).is_err() {
+                decoded.clear();
+                DeflateDecoder::new(body).read_to_end(&mut decoded).ok()?;
+            }
+        }
+        _ => return None,
+    }
+    Some(decoded)
+}
+
+/// Decode a chunked body as it was received by the crawler
+fn dechunk(mut body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len());
+    while let Some(end) = body.windows(2).position(|w| w == b"\r\n") {
+        let size_line = String::from_utf8_lossy(&body[..end]);
+        let size_hex = size_line.split(';').next().unwrap_or("").trim();
+        let Ok(size) = usize::from_str_radix(size_hex, 16) else {
+            break;
+        };
+        body = &body[end + 2..];
+        if size == 0 || size > body.len() {
+            out.extend_from_slice(&body[..size.min(body.len())]);
+            break;
+        }
+        out.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or(&[]);
+    }
+    out
+}
@@ -147,6 +147,29 @@ assert_contains "$output" "-f, --foreground" "--help shows -f flag"
 assert_contains "$output" "-m, --menu" "--help shows -m flag"
 assert_contains "$output" "-p, --prompt" "--help shows -p flag"
 assert_contains "$output" "-r, --root" "--help shows -r flag"
+assert_contains "$output" "-b, --browser" "--help shows -b flag"
+assert_contains "$output" "-n, --no-open" "--help shows -n flag"
+assert_contains "$output" "--print-url" "--help shows --print-url flag"
+assert_contains "$output" "--json" "--help shows --json flag"
+assert_contains "$output" "--log-format" "--help shows --log-format option"
+assert_contains "$output" "logs" "--help shows the logs command"
+assert_contains "$output" "--rewrite-origin" "--help shows --rewrite-origin option"
+assert_contains "$output" "--domains" "--help shows --domains option"
+assert_contains "$output" "--mirror-names" "--help shows --mirror-names option"
+assert_contains "$output" "--ignore-case" "--help shows --ignore-case flag"
+assert_contains "$output" "--normalize-unicode" "--help shows --normalize-unicode flag"
+assert_contains "$output" "check" "--help shows the check command"
+assert_contains "$output" "sitemap" "--help shows the sitemap command"
+assert_contains "$output" "-a, --action" "--help shows -a flag"
+assert_contains "$output" "-y, --yes" "--help shows -y flag"
+assert_contains "$output" "--bind <ADDR>" "--help shows --bind flag"
+assert_contains "$output" "--lan" "--help shows --lan flag"
+assert_contains "$output" "--https" "--help shows --https flag"
+assert_contains "$output" "--auth <USER:PASS>" "--help shows --auth flag"
+assert_contains "$output" "--token" "--help shows --token flag"
+assert_contains "$output" "--allow-host <HOST>" "--help shows --allow-host flag"
+assert_contains "$output" "--allow-dotfile <NAME>" "--help shows --allow-dotfile flag"
+assert_contains "$output" "--follow-symlinks" "--help shows --follow-symlinks flag"
 
 echo ""
 
@@ -403,6 +426,1238 @@ cleanup_servers
 
 echo ""
 
+# ============================================================================
+# Section 10: Browser Selection Tests
+# ============================================================================
+echo -e "${BOLD}--- Browser Selection Tests (-b flag) ---${NC}"
+
+cleanup_servers
+cd "$URL_TEST_DIR"
+BROWSER_OUT=$(mktemp)
+
+# Test: {url} placeholder is replaced in the browser command
+"$HOPEN_BIN" -r "$URL_TEST_DIR" -b "echo {url} > $BROWSER_OUT" index.html >/dev/null 2>&1
+sleep 0.5
+assert_equals "http://localhost:8000/index.html" "$(cat "$BROWSER_OUT")" "-b runs command with {url} replaced"
+
+# Test: HOPEN_BROWSER is used when -b is not given (reuses the running server)
+: > "$BROWSER_OUT"
+HOPEN_BROWSER="echo {url} > $BROWSER_OUT" "$HOPEN_BIN" -r "$URL_TEST_DIR" index.html >/dev/null 2>&1
+sleep 0.5
+assert_equals "http://localhost:8000/index.html" "$(cat "$BROWSER_OUT")" "HOPEN_BROWSER sets the browser command"
+
+# Test: a browser command that fails is reported instead of "Browser opened"
+output=$("$HOPEN_BIN" -r "$URL_TEST_DIR" -b "false {url}" index.html 2>&1)
+assert_exit_code "1" "$?" "-b with a failing command exits with an error"
+assert_not_contains "$output" "Browser opened" "-b with a failing command doesn't claim the browser opened"
+
+cleanup_servers
+rm -f "$BROWSER_OUT"
+
+echo ""
+
+# ============================================================================
+# Section 11: Scripting Output Tests
+# ============================================================================
+echo -e "${BOLD}--- Scripting Output Tests (--no-open, --print-url) ---${NC}"
+
+cleanup_servers
+cd "$URL_TEST_DIR/blog"
+
+# Test: --print-url prints only the URL on stdout when starting a server
+output=$("$HOPEN_BIN" -r "$URL_TEST_DIR" --no-open --print-url index.html 2>/dev/null)
+assert_equals "http://localhost:8000/blog/index.html" "$output" "--print-url prints only the URL when starting"
+
+# Test: --print-url prints only the URL on stdout when reusing a server
+output=$("$HOPEN_BIN" -r "$URL_TEST_DIR" --no-open --print-url index.html 2>/dev/null)
+assert_equals "http://localhost:8000/blog/index.html" "$output" "--print-url prints only the URL when reusing"
+
+# Test: status messages still go to stderr
+output=$("$HOPEN_BIN" -r "$URL_TEST_DIR" --no-open --print-url 2>&1 >/dev/null)
+assert_contains "$output" "Reusing existing server" "--print-url sends status messages to stderr"
+
+# Test: --no-open does not report opening a browser
+output=$("$HOPEN_BIN" -r "$URL_TEST_DIR" --no-open 2>&1)
+assert_not_contains "$output" "Browser opened" "--no-open skips opening the browser"
+
+cleanup_servers
+
+echo ""
+
+# ============================================================================
+# Section 12: JSON Output Tests
+# ============================================================================
+echo -e "${BOLD}--- JSON Output Tests (--json) ---${NC}"
+
+cleanup_servers
+cd "$URL_TEST_DIR"
+
+# Test: -e with no server reports not_running
+output=$("$HOPEN_BIN" --json -e 2>/dev/null)
+assert_contains "$output" '"action":"not_running"' "--json -e reports not_running"
+
+# Test: starting a server reports the action, port, url and log path
+output=$("$HOPEN_BIN" --json --no-open -r "$URL_TEST_DIR" index.html 2>/dev/null)
+assert_contains "$output" '"action":"started"' "--json reports started"
+assert_contains "$output" '"port":8000' "--json reports the port"
+assert_contains "$output" '"url":"http://localhost:8000/index.html"' "--json reports the url"
+assert_contains "$output" '"log":"' "--json reports the log path"
+
+# Test: reusing a server reports reused with the served root
+output=$("$HOPEN_BIN" --json --no-open 2>/dev/null)
+assert_contains "$output" '"action":"reused"' "--json reports reused"
+assert_contains "$output" "\"root\":\"$URL_TEST_DIR\"" "--json reports the served root"
+
+# Test: stopping a server reports stopped
+output=$("$HOPEN_BIN" --json -e 2>/dev/null)
+assert_contains "$output" '"action":"stopped"' "--json -e reports stopped"
+
+# Test: errors are reported as JSON with a non-zero exit code
+cd "$NO_HTML_DIR"
+output=$("$HOPEN_BIN" --json 2>/dev/null)
+assert_exit_code "1" "$?" "--json error exits non-zero"
+assert_contains "$output" '"action":"error"' "--json reports errors"
+
+cleanup_servers
+
+echo ""
+
+# ============================================================================
+# Section 13: Non-Interactive Menu Tests
+# ============================================================================
+echo -e "${BOLD}--- Non-Interactive Menu Tests (--action, --yes) ---${NC}"
+
+cleanup_servers
+cd "$URL_TEST_DIR"
+
+# Test: -m without a terminal fails instead of waiting for input
+output=$("$HOPEN_BIN" -m --no-open < /dev/null 2>&1)
+assert_exit_code "1" "$?" "-m without a terminal exits non-zero"
+assert_contains "$output" "use --action" "-m without a terminal suggests --action"
+
+# Test: --action open starts a server when none is running
+output=$("$HOPEN_BIN" --action open --no-open --json < /dev/null 2>/dev/null)
+assert_contains "$output" '"action":"started"' "--action open starts a server"
+
+# Test: --action cancel leaves the running server alone
+output=$("$HOPEN_BIN" --action cancel --json < /dev/null 2>/dev/null)
+assert_contains "$output" '"action":"cancelled"' "--action cancel makes no changes"
+
+# Test: -p without a terminal does not hang and does not open the browser
+BROWSER_OUT=$(mktemp)
+output=$("$HOPEN_BIN" -p -b "echo {url} > $BROWSER_OUT" < /dev/null 2>&1)
+sleep 0.5
+assert_equals "" "$(cat "$BROWSER_OUT")" "-p without a terminal does not open the browser"
+
+# Test: --yes answers the -p prompt
+output=$("$HOPEN_BIN" -p --yes -b "echo {url} > $BROWSER_OUT" < /dev/null 2>&1)
+sleep 0.5
+assert_equals "http://localhost:8000" "$(cat "$BROWSER_OUT")" "--yes answers the -p prompt"
+rm -f "$BROWSER_OUT"
+
+# Test: --action quit stops the running server
+output=$("$HOPEN_BIN" --action quit --json < /dev/null 2>/dev/null)
+assert_contains "$output" '"action":"stopped"' "--action quit stops the server"
+
+cleanup_servers
+
+echo ""
+
+# ============================================================================
+# Section 14: Bind Address Tests
+# ============================================================================
+echo -e "${BOLD}--- Bind Address Tests (--bind, --lan) ---${NC}"
+
+cleanup_servers
+cd "$URL_TEST_DIR"
+
+# Test: invalid bind addresses are rejected
+output=$("$HOPEN_BIN" --bind not-an-ip --no-open 2>&1)
+assert_exit_code "2" "$?" "--bind rejects invalid addresses"
+
+# Test: --bind uses the bound address in the URL when it isn't localhost
+# (127.0.0.2 is only routable on Linux; macOS configures just 127.0.0.1)
+if python3 -c "import socket; socket.socket().bind(('127.0.0.2', 0))" 2>/dev/null; then
+    output=$("$HOPEN_BIN" --bind 127.0.0.2 --no-open --print-url 2>/dev/null)
+    assert_equals "http://127.0.0.2:8000" "$output" "--bind uses the bound address in the URL"
+    response=$(curl -s -o /dev/null -w "%{http_code}" http://127.0.0.2:8000/index.html 2>/dev/null || echo "000")
+    assert_equals "200" "$response" "--bind serves on the given address"
+    cleanup_servers
+else
+    echo -e "${YELLOW}⚠ SKIP${NC}: 127.0.0.2 not available, skipping --bind address tests"
+fi
+
+# Test: --lan listens on all interfaces and lists network URLs
+output=$("$HOPEN_BIN" --lan --no-auth --no-open 2>&1)
+assert_contains "$output" "Access at: http://localhost:8000" "--lan still opens localhost"
+assert_contains "$output" "reachable from other machines" "--lan warns about network exposure"
+response=$(curl -s -o /dev/null -w "%{http_code}" http://127.0.0.1:8000/index.html 2>/dev/null || echo "000")
+assert_equals "200" "$response" "--lan serves over IPv4"
+
+# Test: the network URL is shown as a QR code on startup and via the menu
+if [[ "$output" == *"Network: "* ]]; then
+    assert_contains "$output" "▀" "--lan prints a QR code of the network URL"
+    output=$("$HOPEN_BIN" --action qr --json < /dev/null 2>&1)
+    assert_contains "$output" '"action":"qr"' "--action qr shows the QR code for a running server"
+else
+    echo -e "${YELLOW}⚠ SKIP${NC}: no network interface, skipping QR code tests"
+fi
+
+cleanup_servers
+
+# Test: --action qr with no running server doesn't start one
+output=$("$HOPEN_BIN" --action qr --json < /dev/null 2>/dev/null)
+assert_contains "$output" '"action":"not_running"' "--action qr without a server reports not_running"
+response=$(curl -s -o /dev/null -w "%{http_code}" http://127.0.0.1:8000/ 2>/dev/null)
+assert_equals "000" "$response" "--action qr without a server starts nothing"
+
+echo ""
+
+# ============================================================================
+# Section 15: HTTPS Tests
+# ============================================================================
+echo -e "${BOLD}--- HTTPS Tests (--https, --cert, --key) ---${NC}"
+
+cleanup_servers
+cd "$URL_TEST_DIR"
+CACHE_DIR=$(mktemp -d)
+
+# Test: --cert requires --key
+output=$("$HOPEN_BIN" --cert "$CACHE_DIR/cert.pem" --no-open 2>&1)
+assert_exit_code "2" "$?" "--cert without --key is rejected"
+
+# Test: --https generates a certificate and serves over TLS
+output=$(XDG_CACHE_HOME="$CACHE_DIR" "$HOPEN_BIN" --https --no-open --print-url 2>/dev/null)
+assert_equals "https://localhost:8000" "$output" "--https uses an https URL"
+response=$(curl -sk -o /dev/null -w "%{http_code}" https://localhost:8000/index.html 2>/dev/null || echo "000")
+assert_equals "200" "$response" "--https serves over TLS"
+assert_equals "yes" "$([[ -f "$CACHE_DIR/hopen/tls/cert.pem" ]] && echo yes)" "--https caches the generated certificate"
+
+# Test: reusing an HTTPS server keeps the https scheme
+output=$("$HOPEN_BIN" --no-open --print-url 2>/dev/null)
+assert_equals "https://localhost:8000" "$output" "Reusing an HTTPS server keeps the https URL"
+cleanup_servers
+
+# Test: --cert/--key serve with the supplied certificate
+output=$("$HOPEN_BIN" --cert "$CACHE_DIR/hopen/tls/cert.pem" --key "$CACHE_DIR/hopen/tls/key.pem" --no-open --print-url 2>/dev/null)
+response=$(curl -sk -o /dev/null -w "%{http_code}" https://localhost:8000/index.html 2>/dev/null || echo "000")
+assert_equals "200" "$response" "--cert/--key serve over TLS"
+
+cleanup_servers
+rm -rf "$CACHE_DIR"
+
+echo ""
+
+# ============================================================================
+# Section 16: Authentication Tests
+# ============================================================================
+echo -e "${BOLD}--- Authentication Tests (--auth, --token, --no-auth) ---${NC}"
+
+cleanup_servers
+cd "$URL_TEST_DIR"
+
+# Test: --auth requires USER:PASS
+output=$("$HOPEN_BIN" --auth nocolon --no-open 2>&1)
+assert_exit_code "2" "$?" "--auth rejects values without a colon"
+
+# Test: basic auth rejects anonymous requests and accepts the credentials
+"$HOPEN_BIN" --auth user:secret --no-open >/dev/null 2>&1
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/index.html 2>/dev/null || echo "000")
+assert_equals "401" "$response" "--auth rejects requests without credentials"
+response=$(curl -s -o /dev/null -w "%{http_code}" -u user:secret http://localhost:8000/index.html 2>/dev/null || echo "000")
+assert_equals "200" "$response" "--auth accepts the configured credentials"
+
+# Test: the credentials aren't on the background server's command line
+assert_not_contains "$(ps -eo args | grep internal-ser[v]e)" "user:secret" "--auth credentials don't show up in ps"
+
+# Test: reusing the server keeps its credentials
+output=$("$HOPEN_BIN" --auth user:secret --no-open 2>&1)
+assert_contains "$output" "Reusing existing server" "Reusing a server with the same --auth credentials"
+cleanup_servers
+
+# Test: --lan requires an access token by default
+url=$("$HOPEN_BIN" -r "$URL_TEST_DIR" --lan --no-open --print-url index.html 2>/dev/null)
+assert_contains "$url" "/index.html?token=" "--lan adds an access token to the URL"
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/index.html 2>/dev/null || echo "000")
+assert_equals "401" "$response" "--lan rejects requests without the token"
+
+# Test: the token URL sets a cookie and redirects, after which the cookie is enough
+COOKIE_JAR=$(mktemp)
+response=$(curl -s -o /dev/null -w "%{http_code}" -c "$COOKIE_JAR" "$url" 2>/dev/null || echo "000")
+assert_equals "302" "$response" "Token URL redirects after setting the cookie"
+response=$(curl -s -o /dev/null -w "%{http_code}" -b "$COOKIE_JAR" http://localhost:8000/index.html 2>/dev/null || echo "000")
+assert_equals "200" "$response" "Token cookie grants access"
+rm -f "$COOKIE_JAR"
+
+# Test: reusing the server returns the URL with its token
+output=$("$HOPEN_BIN" -r "$URL_TEST_DIR" --no-open --print-url index.html 2>/dev/null)
+assert_equals "$url" "$output" "Reusing a token-protected server keeps its token"
+cleanup_servers
+
+# Test: reusing a foreground token server returns a URL with its token
+"$HOPEN_BIN" -r "$URL_TEST_DIR" -f --token --no-open >/dev/null 2>&1 &
+FG_PID=$!
+sleep 1
+url=$("$HOPEN_BIN" -r "$URL_TEST_DIR" --no-open --print-url 2>/dev/null)
+assert_contains "$url" "?token=" "Reusing a foreground token server keeps its token"
+response=$(curl -s -o /dev/null -w "%{http_code}" "$url" 2>/dev/null)
+assert_equals "302" "$response" "The URL printed for a reused foreground token server is accepted"
+kill "$FG_PID" 2>/dev/null
+wait "$FG_PID" 2>/dev/null
+cleanup_servers
+
+# Test: --no-auth serves the LAN without a token
+url=$("$HOPEN_BIN" --lan --no-auth --no-open --print-url 2>/dev/null)
+assert_not_contains "$url" "token=" "--no-auth disables the access token"
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/index.html 2>/dev/null || echo "000")
+assert_equals "200" "$response" "--no-auth serves without authentication"
+
+cleanup_servers
+
+echo ""
+
+# ============================================================================
+# Section 17: Host Header Validation Tests
+# ============================================================================
+echo -e "${BOLD}--- Host Header Validation Tests (--allow-host) ---${NC}"
+
+cleanup_servers
+cd "$URL_TEST_DIR"
+
+"$HOPEN_BIN" --no-open --allow-host preview.test >/dev/null 2>&1
+
+# Test: loopback host names are accepted
+response=$(curl -s -o /dev/null -w "%{http_code}" -H "Host: localhost:8000" http://127.0.0.1:8000/index.html 2>/dev/null || echo "000")
+assert_equals "200" "$response" "Host localhost is accepted"
+response=$(curl -s -o /dev/null -w "%{http_code}" -H "Host: 127.0.0.1:8000" http://127.0.0.1:8000/index.html 2>/dev/null || echo "000")
+assert_equals "200" "$response" "Host 127.0.0.1 is accepted"
+
+# Test: other host names are rejected (DNS rebinding protection)
+response=$(curl -s -o /dev/null -w "%{http_code}" -H "Host: attacker.example:8000" http://127.0.0.1:8000/index.html 2>/dev/null || echo "000")
+assert_equals "421" "$response" "Unknown Host is rejected with 421"
+
+# Test: --allow-host adds host names
+response=$(curl -s -o /dev/null -w "%{http_code}" -H "Host: preview.test:8000" http://127.0.0.1:8000/index.html 2>/dev/null || echo "000")
+assert_equals "200" "$response" "--allow-host accepts the configured host"
+
+cleanup_servers
+
+echo ""
+
+# ============================================================================
+# Section 18: Hidden File and Symlink Tests
+# ============================================================================
+echo -e "${BOLD}--- Hidden File and Symlink Tests (--allow-dotfile, --follow-symlinks) ---${NC}"
+
+cleanup_servers
+
+POLICY_DIR=$(mktemp -d)
+OUTSIDE_DIR=$(mktemp -d)
+mkdir -p "$POLICY_DIR/.git" "$POLICY_DIR/.well-known"
+echo "<html></html>" > "$POLICY_DIR/index.html"
+echo "SECRET=1" > "$POLICY_DIR/.env"
+echo "[core]" > "$POLICY_DIR/.git/config"
+echo "Contact: me" > "$POLICY_DIR/.well-known/security.txt"
+echo "<html>outside</html>" > "$OUTSIDE_DIR/outside.html"
+ln -s "$OUTSIDE_DIR/outside.html" "$POLICY_DIR/escape.html"
+ln -s index.html "$POLICY_DIR/alias.html"
+cd "$POLICY_DIR"
+
+"$HOPEN_BIN" --no-open >/dev/null 2>&1
+
+# Test: dotfiles are hidden by default
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/.env 2>/dev/null || echo "000")
+assert_equals "404" "$response" "Dotfiles are hidden by default"
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/.git/config 2>/dev/null || echo "000")
+assert_equals "404" "$response" "Files in hidden directories are hidden"
+
+# Test: .well-known is served
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/.well-known/security.txt 2>/dev/null || echo "000")
+assert_equals "200" "$response" ".well-known is served by default"
+
+# Test: symlinks inside the root are followed, symlinks escaping it are refused
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/alias.html 2>/dev/null || echo "000")
+assert_equals "200" "$response" "Symlinks within the root are served"
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/escape.html 2>/dev/null || echo "000")
+assert_equals "403" "$response" "Symlinks escaping the root are refused"
+cleanup_servers
+
+# Test: --allow-dotfile and --follow-symlinks relax the policy
+"$HOPEN_BIN" --no-open --allow-dotfile .env --follow-symlinks >/dev/null 2>&1
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/.env 2>/dev/null || echo "000")
+assert_equals "200" "$response" "--allow-dotfile serves the named dotfile"
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/escape.html 2>/dev/null || echo "000")
+assert_equals "200" "$response" "--follow-symlinks serves symlinks escaping the root"
+
+cleanup_servers
+rm -rf "$POLICY_DIR" "$OUTSIDE_DIR"
+
+echo ""
+
+# ============================================================================
+# Section 19: Access Log Tests
+# ============================================================================
+echo -e "${BOLD}--- Access Log Tests (--log-format) ---${NC}"
+
+cleanup_servers
+cd "$URL_TEST_DIR"
+
+# Test: background servers log requests to their log file
+output=$("$HOPEN_BIN" --json --no-open 2>/dev/null)
+log_file=$(echo "$output" | python3 -c 'import json, sys; print(json.load(sys.stdin)["log"])')
+curl -s -o /dev/null http://localhost:8000/index.html 2>/dev/null
+curl -s -o /dev/null "http://localhost:8000/missing.html?from=test" 2>/dev/null
+sleep 0.2
+log=$(cat "$log_file" 2>/dev/null)
+assert_contains "$log" '"GET /index.html" 200' "Access log records the request and status"
+assert_contains "$log" '"GET /missing.html?from=test" 404' "Access log records missing files with the query"
+cleanup_servers
+
+# Test: --log-format json writes one JSON object per request
+output=$("$HOPEN_BIN" --json --no-open --log-format json 2>/dev/null)
+log_file=$(echo "$output" | python3 -c 'import json, sys; print(json.load(sys.stdin)["log"])')
+curl -s -o /dev/null http://localhost:8000/index.html 2>/dev/null
+sleep 0.2
+log=$(grep '^{' "$log_file" 2>/dev/null | tail -1)
+assert_contains "$log" '"path":"/index.html","status":200' "JSON access log records path and status"
+assert_contains "$log" '"duration_ms":' "JSON access log records the duration"
+cleanup_servers
+
+echo ""
+
+# ============================================================================
+# Section 20: Logs Command Tests
+# ============================================================================
+echo -e "${BOLD}--- Logs Command Tests (hopen logs) ---${NC}"
+
+cleanup_servers
+cd "$URL_TEST_DIR"
+
+# Test: no server, nothing to show
+output=$("$HOPEN_BIN" logs 2>&1)
+assert_exit_code "1" "$?" "hopen logs fails when no server is running"
+assert_contains "$output" "No hopen server is running" "hopen logs explains that no server is running"
+
+"$HOPEN_BIN" --no-open >/dev/null 2>&1
+curl -s -o /dev/null http://localhost:8000/index.html 2>/dev/null
+sleep 0.2
+
+# Test: the log is found by port, by root and from inside the root
+output=$("$HOPEN_BIN" logs 8000 2>/dev/null)
+assert_contains "$output" '"GET /index.html" 200' "hopen logs PORT prints the server's log"
+output=$(cd / && "$HOPEN_BIN" logs "$URL_TEST_DIR" 2>/dev/null)
+assert_contains "$output" '"GET /index.html" 200' "hopen logs ROOT prints the server's log"
+output=$("$HOPEN_BIN" logs 2>/dev/null)
+assert_contains "$output" '"GET /index.html" 200' "hopen logs finds the server for the current directory"
+
+# Test: --json reports where the log is
+output=$("$HOPEN_BIN" logs --json 2>/dev/null)
+assert_contains "$output" '"action":"logs"' "hopen logs --json reports the log"
+assert_contains "$output" '/hopen/logs/' "Logs are kept in the state directory"
+
+# Test: unknown port
+output=$("$HOPEN_BIN" logs 8099 2>&1)
+assert_contains "$output" "No hopen server is running on port 8099" "hopen logs rejects ports without a server"
+
+# Test: -f follows new requests and stops with the server
+follow_file=$(mktemp)
+"$HOPEN_BIN" logs -f 8000 > "$follow_file" 2>/dev/null &
+follow_pid=$!
+sleep 0.5
+curl -s -o /dev/null http://localhost:8000/followed.html 2>/dev/null
+sleep 0.5
+cleanup_servers
+sleep 0.5
+if kill -0 "$follow_pid" 2>/dev/null; then
+    kill "$follow_pid" 2>/dev/null
+    assert_equals "exited" "running" "hopen logs -f stops when the server stops"
+else
+    assert_equals "exited" "exited" "hopen logs -f stops when the server stops"
+fi
+assert_contains "$(cat "$follow_file")" '"GET /followed.html" 404' "hopen logs -f prints new requests"
+rm -f "$follow_file"
+
+echo ""
+
+# ============================================================================
+# Section 21: Log File Tests
+# ============================================================================
+echo -e "${BOLD}--- Log File Tests (--log-max-size, rotation, pruning) ---${NC}"
+
+cleanup_servers
+cd "$URL_TEST_DIR"
+
+LOGS_DIR="${XDG_STATE_HOME:-$HOME/.local/state}/hopen/logs"
+mkdir -p "$LOGS_DIR"
+rm -f "$LOGS_DIR"/server-8000.log*
+
+# Test: logs of servers that stopped long ago are pruned, recent ones are kept
+touch -d "10 days ago" "$LOGS_DIR/server-8098.log" "/tmp/hopen-server-1.log"
+touch "$LOGS_DIR/server-8097.log"
+"$HOPEN_BIN" --no-open >/dev/null 2>&1
+assert_equals "no" "$([[ -e "$LOGS_DIR/server-8098.log" ]] && echo yes || echo no)" "Old logs of stopped servers are pruned"
+assert_equals "no" "$([[ -e /tmp/hopen-server-1.log ]] && echo yes || echo no)" "Old /tmp/hopen-server-*.log files are pruned"
+assert_equals "yes" "$([[ -e "$LOGS_DIR/server-8097.log" ]] && echo yes || echo no)" "Recent logs are kept"
+rm -f "$LOGS_DIR/server-8097.log"
+
+# Test: restarting on the same port appends to the same log
+curl -s -o /dev/null http://localhost:8000/first.html 2>/dev/null
+cleanup_servers
+"$HOPEN_BIN" --no-open >/dev/null 2>&1
+curl -s -o /dev/null http://localhost:8000/second.html 2>/dev/null
+sleep 0.2
+log=$(cat "$LOGS_DIR/server-8000.log" 2>/dev/null)
+assert_contains "$log" "/first.html" "The log keeps requests of the previous server on the port"
+assert_contains "$log" "/second.html" "The log appends requests of the new server"
+cleanup_servers
+
+# Test: the log is rotated once it exceeds --log-max-size
+"$HOPEN_BIN" --no-open --log-max-size 0 >/dev/null 2>&1
+curl -s -o /dev/null http://localhost:8000/rotated.html 2>/dev/null
+curl -s -o /dev/null http://localhost:8000/index.html 2>/dev/null
+sleep 0.2
+assert_contains "$(cat "$LOGS_DIR/server-8000.log.1" 2>/dev/null)" '"GET /index.html" 200' "Rotated log holds the latest requests"
+assert_contains "$(cat "$LOGS_DIR/server-8000.log.2" 2>/dev/null)" "/rotated.html" "Older rotated logs are shifted"
+cleanup_servers
+rm -f "$LOGS_DIR"/server-8000.log*
+
+# Test: the log is private and doesn't record the access token
+url=$("$HOPEN_BIN" --lan --no-open --print-url 2>/dev/null)
+curl -s -o /dev/null "$url&page=2" 2>/dev/null
+sleep 0.2
+log=$(cat "$LOGS_DIR/server-8000.log" 2>/dev/null)
+assert_contains "$log" '"GET /?page=2" 302' "The log keeps the rest of the query"
+assert_not_contains "$log" "token=" "The access token isn't written to the log"
+assert_equals "600" "$(stat -c %a "$LOGS_DIR/server-8000.log" 2>/dev/null || stat -f %Lp "$LOGS_DIR/server-8000.log")" "The log is only readable by its owner"
+cleanup_servers
+rm -f "$LOGS_DIR"/server-8000.log*
+
+echo ""
+
+# ============================================================================
+# Section 22: Missing Resource Report Tests
+# ============================================================================
+echo -e "${BOLD}--- Missing Resource Report Tests (hopen report missing, /__hopen/missing) ---${NC}"
+
+cleanup_servers
+cd "$URL_TEST_DIR"
+
+"$HOPEN_BIN" --no-open >/dev/null 2>&1
+
+# Test: a fresh server has nothing missing
+output=$("$HOPEN_BIN" report missing 2>/dev/null)
+assert_contains "$output" "No missing resources" "hopen report missing starts empty"
+
+curl -s -o /dev/null -H "Referer: http://localhost:8000/index.html" http://localhost:8000/img/logo.png 2>/dev/null
+curl -s -o /dev/null -H "Referer: http://localhost:8000/about.html" http://localhost:8000/img/logo.png 2>/dev/null
+curl -s -o /dev/null http://localhost:8000/style.css 2>/dev/null
+curl -s -o /dev/null http://localhost:8000/index.html 2>/dev/null
+sleep 0.2
+
+# Test: 404s are aggregated by path and referrer
+output=$("$HOPEN_BIN" report missing 2>/dev/null)
+assert_contains "$output" "/img/logo.png" "Report lists missing paths"
+assert_contains "$output" "http://localhost:8000/about.html (1)" "Report lists referrers with counts"
+output=$("$HOPEN_BIN" report missing --json 2>/dev/null)
+assert_contains "$output" '"path":"/img/logo.png","count":2' "JSON report counts requests per path"
+assert_not_contains "$output" '"path":"/index.html"' "Report leaves out files that were found"
+
+# Test: the live page
+output=$(curl -s http://localhost:8000/__hopen/missing 2>/dev/null)
+assert_contains "$output" "<code>/style.css</code>" "/__hopen/missing lists missing paths"
+output=$(curl -s "http://localhost:8000/__hopen/missing?format=json" 2>/dev/null)
+assert_contains "$output" '"path": "/img/logo.png"' "/__hopen/missing?format=json returns JSON"
+
+# Test: the report is still available by port after the server stops
+cleanup_servers
+output=$("$HOPEN_BIN" report missing 8000 2>/dev/null)
+assert_contains "$output" "/style.css" "hopen report missing PORT works after the server stopped"
+
+# Test: the report keeps a bounded number of paths
+"$HOPEN_BIN" --no-open >/dev/null 2>&1
+curl -s -o /dev/null "http://localhost:8000/gone-[1-1010].png" 2>/dev/null
+sleep 0.5
+output=$(curl -s "http://localhost:8000/__hopen/missing?format=json" 2>/dev/null)
+assert_equals "1000" "$(grep -c '"path":' <<< "$output")" "/__hopen/missing keeps at most 1000 paths"
+output=$("$HOPEN_BIN" report missing --json 2>/dev/null)
+assert_contains "$output" '"path":"/gone-1000.png"' "The capped report is saved"
+assert_not_contains "$output" '"path":"/gone-1001.png"' "Paths past the cap aren't saved"
+cleanup_servers
+
+echo ""
+
+# ============================================================================
+# Section 23: Origin Rewriting Tests
+# ============================================================================
+echo -e "${BOLD}--- Origin Rewriting Tests (--rewrite-origin) ---${NC}"
+
+cleanup_servers
+
+MIRROR_DIR=$(mktemp -d)
+mkdir -p "$MIRROR_DIR/css"
+cat > "$MIRROR_DIR/index.html" <<'HTML'
+<a href="https://www.example.com/about.html">about</a>
+<a href="http://www.example.com">home</a>
+<img src="//www.example.com/img/logo.png">
+<a href="https://www.example.com.evil.test/x">other host</a>
+<script>var api = "https:\/\/www.example.com\/api\/data.json";</script>
+HTML
+echo 'body { background: url(https://www.example.com/bg.png); }' > "$MIRROR_DIR/css/site.css"
+echo 'https://www.example.com/notes.txt' > "$MIRROR_DIR/notes.txt"
+cd "$MIRROR_DIR"
+
+# Test: without the option files are served unchanged
+"$HOPEN_BIN" --no-open >/dev/null 2>&1
+output=$(curl -s http://localhost:8000/index.html 2>/dev/null)
+assert_contains "$output" 'href="https://www.example.com/about.html"' "Links are left alone by default"
+cleanup_servers
+
+"$HOPEN_BIN" --no-open --rewrite-origin https://www.example.com/ >/dev/null 2>&1
+
+# Test: absolute links in HTML become local paths
+output=$(curl -s http://localhost:8000/index.html 2>/dev/null)
+assert_contains "$output" 'href="/about.html"' "https links are rewritten"
+assert_contains "$output" 'href="/"' "A bare origin becomes the site root"
+assert_contains "$output" 'src="/img/logo.png"' "Protocol-relative links are rewritten"
+assert_contains "$output" 'https://www.example.com.evil.test/x' "Longer host names are left alone"
+assert_contains "$output" '"\/api\/data.json"' "Escaped URLs in scripts are rewritten"
+
+# Test: CSS is rewritten, other files are not, and the file on disk is untouched
+output=$(curl -s http://localhost:8000/css/site.css 2>/dev/null)
+assert_contains "$output" 'url(/bg.png)' "URLs in CSS are rewritten"
+output=$(curl -s http://localhost:8000/notes.txt 2>/dev/null)
+assert_contains "$output" 'https://www.example.com/notes.txt' "Plain text files are not rewritten"
+assert_contains "$(cat "$MIRROR_DIR/index.html")" 'https://www.example.com/about.html' "Files on disk are not modified"
+
+# Test: Content-Length matches the rewritten body
+length=$(curl -s -o /dev/null -w "%{size_download}" http://localhost:8000/index.html 2>/dev/null)
+header=$(curl -s -D - -o /dev/null http://localhost:8000/index.html 2>/dev/null | tr -d '\r' | awk -F': ' 'tolower($1)=="content-length" {print $2}')
+assert_equals "$length" "$header" "Content-Length matches the rewritten body"
+
+# Test: invalid origins are rejected
+output=$("$HOPEN_BIN" --no-open --rewrite-origin www.example.com 2>&1)
+assert_exit_code "2" "$?" "--rewrite-origin requires an http(s) URL"
+
+cleanup_servers
+rm -rf "$MIRROR_DIR"
+
+echo ""
+
+# ============================================================================
+# Section 24: Multi-Domain Mirror Tests
+# ============================================================================
+echo -e "${BOLD}--- Multi-Domain Mirror Tests (--domains) ---${NC}"
+
+cleanup_servers
+
+DOMAINS_DIR=$(mktemp -d)
+mkdir -p "$DOMAINS_DIR/www.example.com/css" "$DOMAINS_DIR/cdn.example.com/js"
+cat > "$DOMAINS_DIR/www.example.com/index.html" <<'HTML'
+<link href="/css/site.css" rel="stylesheet">
+<script src="https://cdn.example.com/js/app.js"></script>
+HTML
+echo 'body {}' > "$DOMAINS_DIR/www.example.com/css/site.css"
+echo 'var app = 1;' > "$DOMAINS_DIR/cdn.example.com/js/app.js"
+cd "$DOMAINS_DIR"
+
+# Test: a mirror root without HTML files is accepted when it has domain folders
+output=$("$HOPEN_BIN" --no-open --domains prefix 2>&1)
+assert_contains "$output" "www.example.com: /www.example.com/" "--domains prefix prints each domain's path"
+
+# Test: path prefixes
+output=$(curl -s http://localhost:8000/ 2>/dev/null)
+assert_contains "$output" 'href="/cdn.example.com/"' "/ lists the domains"
+output=$(curl -s http://localhost:8000/www.example.com/index.html 2>/dev/null)
+assert_contains "$output" 'src="/cdn.example.com/js/app.js"' "Cross-domain links point at the domain's folder"
+response=$(curl -s -o /dev/null -w "%{http_code} %{redirect_url}" -H "Referer: http://localhost:8000/www.example.com/index.html" http://localhost:8000/css/site.css 2>/dev/null)
+assert_equals "302 http://localhost:8000/www.example.com/css/site.css" "$response" "Root-relative links are redirected into the referring domain"
+
+# Test: the redirect can't be used to probe for files outside the domain folder
+response=$(curl -s --path-as-is -o /dev/null -w "%{http_code}" -H "Referer: http://localhost:8000/www.example.com/index.html" "http://localhost:8000/%2e%2e/cdn.example.com/js/app.js" 2>/dev/null)
+assert_equals "404" "$response" "Referer redirects reject .. segments"
+response=$(curl -s -o /dev/null -w "%{http_code}" -H "Referer: http://localhost:8000/www.example.com/index.html" "http://localhost:8000/%2Fetc%2Fpasswd" 2>/dev/null)
+assert_not_contains "$response" "302" "Referer redirects reject absolute paths"
+mkdir -p "$DOMAINS_DIR/www.example.com/.git"
+echo 'secret' > "$DOMAINS_DIR/www.example.com/.git/config"
+OUTSIDE_FILE=$(mktemp)
+ln -s "$OUTSIDE_FILE" "$DOMAINS_DIR/www.example.com/outside.txt"
+response=$(curl -s -o /dev/null -w "%{http_code}" -H "Referer: http://localhost:8000/www.example.com/index.html" http://localhost:8000/.git/config 2>/dev/null)
+assert_equals "404" "$response" "Referer redirects don't reveal hidden files"
+response=$(curl -s -o /dev/null -w "%{http_code}" -H "Referer: http://localhost:8000/www.example.com/index.html" http://localhost:8000/outside.txt 2>/dev/null)
+assert_not_contains "$response" "302" "Referer redirects don't reveal symlinks out of the root"
+rm -rf "$DOMAINS_DIR/www.example.com/.git" "$DOMAINS_DIR/www.example.com/outside.txt" "$OUTSIDE_FILE"
+cleanup_servers
+
+# Test: virtual hosts
+output=$("$HOPEN_BIN" --no-open --domains vhost 2>&1)
+assert_contains "$output" "http://www.example.com.localhost:8000/" "--domains vhost prints each domain's host"
+output=$(curl -s -H "Host: www.example.com.localhost:8000" http://127.0.0.1:8000/ 2>/dev/null)
+assert_contains "$output" 'src="http://cdn.example.com.localhost:8000/js/app.js"' "Cross-domain links point at the domain's host"
+output=$(curl -s -H "Host: www.example.com.localhost:8000" http://127.0.0.1:8000/css/site.css 2>/dev/null)
+assert_equals "body {}" "$output" "Root-relative paths resolve inside the domain"
+output=$(curl -s -H "Host: cdn.example.com.localhost:8000" http://127.0.0.1:8000/js/app.js 2>/dev/null)
+assert_equals "var app = 1;" "$output" "Each domain is served on its own host"
+response=$(curl -s -o /dev/null -w "%{http_code}" -H "Host: www.example.com.localhost:8000" http://127.0.0.1:8000/js/app.js 2>/dev/null || echo "000")
+assert_equals "404" "$response" "Domains don't see each other's files"
+
+cleanup_servers
+rm -rf "$DOMAINS_DIR"
+
+echo ""
+
+# ============================================================================
+# Section 25: Mirrored Query-String Filenames
+# ============================================================================
+echo -e "${BOLD}--- Mirrored Query-String Filenames (--mirror-names) ---${NC}"
+
+cleanup_servers
+
+MIRROR_DIR=$(mktemp -d)
+echo '<h1>Mirror</h1>' > "$MIRROR_DIR/index.html"
+echo 'page.php itself' > "$MIRROR_DIR/page.php"
+echo '<p>wget 3</p>' > "$MIRROR_DIR/page.php?id=3.html"
+echo '<p>sitesucker 4</p>' > "$MIRROR_DIR/page.php%3Fid=4.html"
+echo '<p>sitesucker 5</p>' > "$MIRROR_DIR/page-id=5.php"
+echo '<p>custom 6</p>' > "$MIRROR_DIR/page_id=6.php"
+cd "$MIRROR_DIR"
+
+"$HOPEN_BIN" --no-open >/dev/null 2>&1
+
+# Test: each tool's file names are found
+output=$(curl -s "http://localhost:8000/page.php?id=3" 2>/dev/null)
+assert_equals "<p>wget 3</p>" "$output" "wget's page.php?id=3.html is served for ?id=3"
+output=$(curl -s "http://localhost:8000/page.php?id=4" 2>/dev/null)
+assert_equals "<p>sitesucker 4</p>" "$output" "SiteSucker's page.php%3Fid=4.html is served for ?id=4"
+output=$(curl -s "http://localhost:8000/page.php?id=5" 2>/dev/null)
+assert_equals "<p>sitesucker 5</p>" "$output" "SiteSucker's page-id=5.php is served for ?id=5"
+
+# Test: saved scripts are served as HTML
+content_type=$(curl -s -o /dev/null -w "%{content_type}" "http://localhost:8000/page.php?id=5" 2>/dev/null)
+assert_contains "$content_type" "text/html" "Saved script output is served as text/html"
+
+# Test: queries without a saved file fall back to the plain file
+output=$(curl -s "http://localhost:8000/page.php?id=7" 2>/dev/null)
+assert_equals "page.php itself" "$output" "Unknown queries serve the file without query"
+output=$(curl -s "http://localhost:8000/page.php?id=/../../../etc/passwd" 2>/dev/null)
+assert_not_contains "$output" "root:" "Queries can't step out of the root"
+cleanup_servers
+
+# Test: custom templates
+"$HOPEN_BIN" --no-open --mirror-names '{dir}{stem}_{query}{ext}' >/dev/null 2>&1
+output=$(curl -s "http://localhost:8000/page.php?id=6" 2>/dev/null)
+assert_equals "<p>custom 6</p>" "$output" "A custom template maps ?id=6 to page_id=6.php"
+output=$(curl -s "http://localhost:8000/page.php?id=3" 2>/dev/null)
+assert_equals "page.php itself" "$output" "Only the given schemes are used"
+cleanup_servers
+
+# Test: none disables the mapping
+"$HOPEN_BIN" --no-open --mirror-names none >/dev/null 2>&1
+output=$(curl -s "http://localhost:8000/page.php?id=3" 2>/dev/null)
+assert_equals "page.php itself" "$output" "--mirror-names none serves files as named"
+cleanup_servers
+
+# Test: invalid values are rejected
+"$HOPEN_BIN" --no-open --mirror-names '{bad}' >/dev/null 2>&1
+assert_exit_code "2" "$?" "--mirror-names rejects unknown schemes"
+
+rm -rf "$MIRROR_DIR"
+
+echo ""
+
+# ============================================================================
+# Section 26: Case-Insensitive Paths
+# ============================================================================
+echo -e "${BOLD}--- Case-Insensitive Path Tests (--ignore-case) ---${NC}"
+
+cleanup_servers
+
+CASE_DIR=$(mktemp -d)
+mkdir -p "$CASE_DIR/images" "$CASE_DIR/Docs" "$CASE_DIR/.git"
+echo '<img src="Images/Logo.PNG">' > "$CASE_DIR/index.html"
+echo 'logo' > "$CASE_DIR/images/logo.png"
+echo 'docs' > "$CASE_DIR/Docs/Index.html"
+echo 'secret' > "$CASE_DIR/.git/config"
+cd "$CASE_DIR"
+
+# Test: case must match by default
+"$HOPEN_BIN" --no-open >/dev/null 2>&1
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/Images/Logo.PNG 2>/dev/null || echo "000")
+assert_equals "404" "$response" "Paths are case-sensitive by default"
+cleanup_servers
+
+"$HOPEN_BIN" --no-open --ignore-case >/dev/null 2>&1
+
+# Test: differently-cased paths resolve
+output=$(curl -s http://localhost:8000/Images/Logo.PNG 2>/dev/null)
+assert_equals "logo" "$output" "--ignore-case serves images/logo.png for Images/Logo.PNG"
+content_type=$(curl -s -o /dev/null -w "%{content_type}" http://localhost:8000/Images/Logo.PNG 2>/dev/null)
+assert_equals "image/png" "$content_type" "Content type comes from the file found"
+output=$(curl -s http://localhost:8000/DOCS/ 2>/dev/null)
+assert_equals "docs" "$output" "Directories resolve to their index page ignoring case"
+
+# Test: still 404 when nothing matches, and hidden files stay hidden
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/images/missing.png 2>/dev/null || echo "000")
+assert_equals "404" "$response" "Paths matching no file are still 404"
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/.GIT/config 2>/dev/null || echo "000")
+assert_equals "404" "$response" "Hidden files aren't reachable through a different case"
+
+# Test: fixups are logged
+output=$("$HOPEN_BIN" logs 8000 2>&1)
+assert_contains "$output" "Case fixup: /Images/Logo.PNG → /images/logo.png" "Each case fixup is logged"
+
+cleanup_servers
+rm -rf "$CASE_DIR"
+
+echo ""
+
+# ============================================================================
+# Section 27: Unicode Normalization
+# ============================================================================
+echo -e "${BOLD}--- Unicode Normalization Tests (--normalize-unicode, hopen report fixups) ---${NC}"
+
+cleanup_servers
+
+UNICODE_DIR=$(mktemp -d)
+echo '<h1>Unicode</h1>' > "$UNICODE_DIR/index.html"
+# café.html stored decomposed (NFD), as macOS does; résumé.html composed (NFC)
+echo 'nfd cafe' > "$UNICODE_DIR/$(printf 'cafe\xcc\x81.html')"
+echo 'nfc resume' > "$UNICODE_DIR/$(printf 'r\xc3\xa9sum\xc3\xa9.html')"
+cd "$UNICODE_DIR"
+
+# Test: the exact form is required by default
+"$HOPEN_BIN" --no-open >/dev/null 2>&1
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/caf%C3%A9.html 2>/dev/null || echo "000")
+assert_equals "404" "$response" "NFC links to NFD files are 404 by default"
+cleanup_servers
+
+"$HOPEN_BIN" --no-open --normalize-unicode >/dev/null 2>&1
+
+# Test: both directions resolve
+output=$(curl -s http://localhost:8000/caf%C3%A9.html 2>/dev/null)
+assert_equals "nfd cafe" "$output" "--normalize-unicode serves an NFD file for an NFC link"
+output=$(curl -s http://localhost:8000/re%CC%81sume%CC%81.html 2>/dev/null)
+assert_equals "nfc resume" "$output" "--normalize-unicode serves an NFC file for an NFD link"
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/CAF%C3%89.html 2>/dev/null || echo "000")
+assert_equals "404" "$response" "Case still matters without --ignore-case"
+
+# Test: the fixups are reported
+sleep 0.2
+output=$("$HOPEN_BIN" report fixups 2>/dev/null)
+assert_contains "$output" "/caf%C3%A9.html" "hopen report fixups lists the normalized paths"
+assert_contains "$output" "(unicode)" "hopen report fixups shows the kind of fixup"
+output=$(curl -s "http://localhost:8000/__hopen/fixups?format=json" 2>/dev/null)
+assert_contains "$output" '"kind": "unicode"' "/__hopen/fixups?format=json returns JSON"
+output=$("$HOPEN_BIN" logs 8000 2>&1)
+assert_contains "$output" "Unicode fixup: /caf%C3%A9.html" "Each Unicode fixup is logged"
+cleanup_servers
+
+# Test: combined with --ignore-case
+"$HOPEN_BIN" --no-open --normalize-unicode --ignore-case >/dev/null 2>&1
+output=$(curl -s http://localhost:8000/CAF%C3%89.html 2>/dev/null)
+assert_equals "nfd cafe" "$output" "Case and normalization are fixed together"
+output=$("$HOPEN_BIN" --json report fixups 8000 2>/dev/null)
+assert_contains "$output" '"kind":"case+unicode"' "Combined fixups are reported as case+unicode"
+
+cleanup_servers
+rm -rf "$UNICODE_DIR"
+
+echo ""
+
+# ============================================================================
+# Section 28: Archives
+# ============================================================================
+echo -e "${BOLD}--- Archive Tests (-r site.zip, .tar, .tar.gz) ---${NC}"
+
+cleanup_servers
+
+ARCHIVE_DIR=$(mktemp -d)
+mkdir -p "$ARCHIVE_DIR/site/css" "$ARCHIVE_DIR/site/docs" "$ARCHIVE_DIR/site/.git" "$ARCHIVE_DIR/elsewhere"
+echo '<h1>Archived</h1>' > "$ARCHIVE_DIR/site/index.html"
+echo 'body {}' > "$ARCHIVE_DIR/site/css/site.css"
+echo 'notes' > "$ARCHIVE_DIR/site/docs/notes.txt"
+echo '0123456789' > "$ARCHIVE_DIR/site/docs/digits.txt"
+echo 'secret' > "$ARCHIVE_DIR/site/.git/config"
+(cd "$ARCHIVE_DIR" && python3 -m zipfile -c site.zip site && tar czf site.tar.gz site && tar cf site.tar -C site .)
+# Archives can be served from any directory, even one without HTML files
+cd "$ARCHIVE_DIR/elsewhere"
+
+"$HOPEN_BIN" --no-open -r "$ARCHIVE_DIR/site.zip" >/dev/null 2>&1
+
+# Test: entries are served, with the single top folder as root
+output=$(curl -s http://localhost:8000/ 2>/dev/null)
+assert_equals "<h1>Archived</h1>" "$output" "-r site.zip serves index.html from the archive"
+output=$(curl -s http://localhost:8000/css/site.css 2>/dev/null)
+assert_equals "body {}" "$output" "Archive entries are served by path"
+content_type=$(curl -s -o /dev/null -w "%{content_type}" http://localhost:8000/css/site.css 2>/dev/null)
+assert_contains "$content_type" "text/css" "Archive entries get their content type"
+
+# Test: range requests
+output=$(curl -s -H "Range: bytes=2-5" http://localhost:8000/docs/digits.txt 2>/dev/null)
+assert_equals "2345" "$output" "Range requests return the requested bytes"
+response=$(curl -s -o /dev/null -w "%{http_code}" -H "Range: bytes=2-5" http://localhost:8000/docs/digits.txt 2>/dev/null || echo "000")
+assert_equals "206" "$response" "Range requests are answered with 206"
+response=$(curl -s -o /dev/null -w "%{http_code}" -H "Range: bytes=100-" http://localhost:8000/docs/digits.txt 2>/dev/null || echo "000")
+assert_equals "416" "$response" "Ranges past the end are answered with 416"
+
+# Test: directory listings
+response=$(curl -s -o /dev/null -w "%{http_code} %{redirect_url}" http://localhost:8000/docs 2>/dev/null)
+assert_equals "301 http://localhost:8000/docs/" "$response" "Folders without trailing slash are redirected"
+output=$(curl -s http://localhost:8000/docs/ 2>/dev/null)
+assert_contains "$output" 'href="digits.txt"' "Folders without index.html are listed"
+
+# Test: hidden and missing entries
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/.git/config 2>/dev/null || echo "000")
+assert_equals "404" "$response" "Hidden archive entries aren't served"
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/missing.html 2>/dev/null || echo "000")
+assert_equals "404" "$response" "Paths not in the archive are 404"
+cleanup_servers
+
+# Test: tar and tar.gz
+"$HOPEN_BIN" --no-open -r "$ARCHIVE_DIR/site.tar" >/dev/null 2>&1
+output=$(curl -s -H "Range: bytes=7-" http://localhost:8000/docs/digits.txt 2>/dev/null)
+assert_equals "789" "$output" "-r site.tar serves ranges of entries"
+cleanup_servers
+"$HOPEN_BIN" --no-open -r "$ARCHIVE_DIR/site.tar.gz" >/dev/null 2>&1
+output=$(curl -s http://localhost:8000/docs/notes.txt 2>/dev/null)
+assert_equals "notes" "$output" "-r site.tar.gz serves entries"
+cleanup_servers
+rm -rf "$ARCHIVE_DIR"
+
+echo ""
+
+# ============================================================================
+# Section 29: WARC Files
+# ============================================================================
+echo -e "${BOLD}--- WARC Tests (hopen capture.warc) ---${NC}"
+
+cleanup_servers
+
+WARC_DIR=$(mktemp -d)
+mkdir -p "$WARC_DIR/elsewhere"
+cd "$WARC_DIR/elsewhere"
+# A small crawl: http:// redirecting to https://, a page linking to a second
+# host, a chunked response, compressed responses, an error page and a redirect
+python3 - "$WARC_DIR/capture.warc" <<'PY'
+import gzip, sys, zlib
+
+def record(kind, uri, block):
+    head = ("WARC/1.0\r\nWARC-Type: %s\r\nWARC-Target-URI: %s\r\n"
+            "Content-Length: %d\r\n\r\n" % (kind, uri, len(block)))
+    return head.encode() + block + b"\r\n\r\n"
+
+def response(uri, status, headers, body):
+    head = "HTTP/1.1 %s\r\n" % status + "".join("%s: %s\r\n" % h for h in headers) + "\r\n"
+    return record("response", uri, head.encode() + body)
+
+page = b'<a href="https://www.example.com/about">About</a><script src="https://cdn.example.com/app.js"></script>'
+data = b"".join([
+    record("warcinfo", "", b"software: test"),
+    record("request", "http://www.example.com/", b"GET / HTTP/1.1\r\n\r\n"),
+    response("http://www.example.com/", "301 Moved Permanently", [("Location", "https://www.example.com/")], b""),
+    response("https://www.example.com/", "200 OK",
+             [("Content-Type", "text/html"), ("X-Archived", "yes"), ("Strict-Transport-Security", "max-age=600")], page),
+    response("https://www.example.com/about", "200 OK",
+             [("Content-Type", "text/plain"), ("Transfer-Encoding", "chunked")], b"5\r\nabout\r\n5\r\n page\r\n0\r\n\r\n"),
+    response("https://www.example.com/about.html", "200 OK",
+             [("Content-Type", "text/html"), ("Content-Encoding", "gzip")],
+             gzip.compress(b'<a href="https://www.example.com/contact">Contact</a>')),
+    response("https://www.example.com/site.css", "200 OK",
+             [("Content-Type", "text/css"), ("Content-Encoding", "deflate")],
+             zlib.compress(b'body { background: url(https://cdn.example.com/bg.png) }')),
+    response("https://www.example.com/gone", "410 Gone", [("Content-Type", "text/plain")], b"gone"),
+    response("https://www.example.com/old", "302 Found", [("Location", "https://www.example.com/about")], b""),
+    response("https://www.example.com/search?q=warc", "200 OK", [("Content-Type", "text/plain")], b"results"),
+    response("https://cdn.example.com/app.js", "200 OK", [("Content-Type", "application/javascript")], b"var app = 1;"),
+])
+open(sys.argv[1], "wb").write(data)
+with gzip.open(sys.argv[1] + ".gz", "wb") as f:
+    f.write(data)
+PY
+
+"$HOPEN_BIN" --no-open ../capture.warc >/dev/null 2>&1
+
+# Test: archived responses are replayed with their headers
+output=$(curl -s http://localhost:8000/ 2>/dev/null)
+assert_contains "$output" 'href="/about"' "hopen capture.warc serves the first host at / with links rewritten"
+assert_contains "$output" 'src="/cdn.example.com/app.js"' "Links to other archived hosts point at /<host>/"
+headers=$(curl -s -D - -o /dev/null http://localhost:8000/ 2>/dev/null)
+assert_contains "$headers" "x-archived: yes" "Original headers are replayed"
+assert_not_contains "$headers" "strict-transport-security" "HSTS headers are dropped"
+
+# Test: original status, chunked bodies and redirects
+output=$(curl -s http://localhost:8000/about 2>/dev/null)
+assert_equals "about page" "$output" "Chunked archived bodies are decoded"
+
+# Test: compressed bodies are decoded so their links can be rewritten
+output=$(curl -s http://localhost:8000/about.html 2>/dev/null)
+assert_equals '<a href="/contact">Contact</a>' "$output" "gzip-encoded archived bodies are decoded and rewritten"
+headers=$(curl -s -D - -o /dev/null http://localhost:8000/about.html 2>/dev/null)
+assert_not_contains "$headers" "content-encoding" "Content-Encoding is dropped once the body is decoded"
+output=$(curl -s http://localhost:8000/site.css 2>/dev/null)
+assert_equals 'body { background: url(/cdn.example.com/bg.png) }' "$output" "deflate-encoded archived bodies are decoded and rewritten"
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/gone 2>/dev/null || echo "000")
+assert_equals "410" "$response" "The original status is replayed"
+response=$(curl -s -o /dev/null -w "%{http_code} %{redirect_url}" http://localhost:8000/old 2>/dev/null)
+assert_equals "302 http://localhost:8000/about" "$response" "Archived redirects stay on the local server"
+output=$(curl -s "http://localhost:8000/search?q=warc" 2>/dev/null)
+assert_equals "results" "$output" "URLs with a query string are matched"
+
+# Test: other hosts and missing URLs
+output=$(curl -s http://localhost:8000/cdn.example.com/app.js 2>/dev/null)
+assert_equals "var app = 1;" "$output" "Other hosts are served under /<host>/"
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/not-captured 2>/dev/null || echo "000")
+assert_equals "404" "$response" "URLs that weren't captured are 404"
+cleanup_servers
+
+# Test: gzipped WARC files
+"$HOPEN_BIN" --no-open -r "$WARC_DIR/capture.warc.gz" >/dev/null 2>&1
+output=$(curl -s http://localhost:8000/about 2>/dev/null)
+assert_equals "about page" "$output" "-r capture.warc.gz serves the archive"
+
+cleanup_servers
+rm -rf "$WARC_DIR"
+
+echo ""
+
+# ============================================================================
+# Section 30: MHTML Files
+# ============================================================================
+echo -e "${BOLD}--- MHTML Tests (hopen page.mhtml) ---${NC}"
+
+cleanup_servers
+
+MHTML_DIR=$(mktemp -d)
+mkdir -p "$MHTML_DIR/elsewhere"
+cd "$MHTML_DIR/elsewhere"
+# A page saved by a browser: quoted-printable HTML, a stylesheet, an image
+# referenced by cid: and one from another host
+python3 - "$MHTML_DIR/page.mhtml" <<'PY'
+import base64, sys
+
+boundary = "----MultipartBoundary--test"
+image = base64.b64encode(b"\x89PNG\r\n\x1a\nimage").decode()
+parts = [
+    ("Content-Type: text/html\nContent-Transfer-Encoding: quoted-printable\n"
+     "Content-Location: https://example.com/page.html",
+     '<link rel=3D"stylesheet" href=3D"https://example.com/style.css"><img src=3D"cid:logo@=\n'
+     'mhtml"><img src=3D"https://cdn.example.org/photo.png">Caf=C3=A9'),
+    ("Content-Type: text/css\nContent-Location: https://example.com/style.css",
+     'body { background: url("cid:logo@mhtml"); }'),
+    ("Content-Type: image/png\nContent-Transfer-Encoding: base64\nContent-ID: <logo@mhtml>", image),
+    ("Content-Type: image/png\nContent-Transfer-Encoding: base64\n"
+     "Content-Location: https://cdn.example.org/photo.png", image),
+]
+text = ("From: <Saved by Blink>\nSnapshot-Content-Location: https://example.com/page.html\n"
+        "MIME-Version: 1.0\nContent-Type: multipart/related;\n\ttype=\"text/html\";\n"
+        "\tboundary=\"%s\"\n\n" % boundary)
+for head, body in parts:
+    text += "--%s\n%s\n\n%s\n" % (boundary, head, body)
+text += "--%s--\n" % boundary
+open(sys.argv[1], "wb").write(text.replace("\n", "\r\n").encode())
+PY
+
+"$HOPEN_BIN" --no-open ../page.mhtml >/dev/null 2>&1
+
+# Test: the main document, with references rewritten
+output=$(curl -s http://localhost:8000/ 2>/dev/null)
+assert_contains "$output" 'href="/style.css"' "hopen page.mhtml serves the page at / with its links rewritten"
+assert_contains "$output" 'src="/__mhtml/2"' "cid: references point at the part"
+assert_contains "$output" 'src="/cdn.example.org/photo.png"' "Parts from other hosts are served under /<host>/"
+assert_contains "$output" "Café" "Quoted-printable parts are decoded"
+
+# Test: the resources
+output=$(curl -s http://localhost:8000/style.css 2>/dev/null)
+assert_contains "$output" 'url("/__mhtml/2")' "References in stylesheets are rewritten"
+headers=$(curl -s -D - -o /dev/null http://localhost:8000/__mhtml/2 2>/dev/null)
+assert_contains "$headers" "content-type: image/png" "Parts are served with their content type"
+output=$(curl -s http://localhost:8000/cdn.example.org/photo.png 2>/dev/null | head -c 4 | tail -c 3)
+assert_equals "PNG" "$output" "Base64 parts are decoded"
+response=$(curl -s -o /dev/null -w "%{http_code}" http://localhost:8000/not-saved 2>/dev/null || echo "000")
+assert_equals "404" "$response" "URLs that aren't parts are 404"
+
+cleanup_servers
+rm -rf "$MHTML_DIR"
+
+echo ""
+
+# ============================================================================
+# Section 31: Link Checker
+# ============================================================================
+echo -e "${BOLD}--- Link Checker Tests (hopen check) ---${NC}"
+
+CHECK_DIR=$(mktemp -d)
+mkdir -p "$CHECK_DIR/site/docs" "$CHECK_DIR/site/css" "$CHECK_DIR/site/img"
+cd "$CHECK_DIR/site"
+cat > index.html <<'HTML'
+<link rel="stylesheet" href="css/site.css">
+<a href="docs/">Docs</a> <a href="docs/page.html#intro">Intro</a>
+<a href="https://www.example.com/docs/page.html">Absolute</a> <a href="https://elsewhere.test/">External</a>
+<a href="docs/page.html#nowhere">Bad anchor</a>
+<a href="missing.html">Missing</a>
+<a href="../outside.html">Outside</a>
+<img srcset="img/logo.png 1x, img/Logo@2x.png 2x">
+<!-- <a href="commented-out.html"> -->
+<script>var link = '<a href="in-script.html">';</script>
+<a href="search.php?q=1">Search</a> <a href="#top">Top</a>
+HTML
+echo '<p id="intro">Intro</p><a href="../index.html">Home</a>' > docs/page.html
+echo '<a href="page.html">Page</a>' > docs/index.html
+echo 'body { background: url("../img/bg.png"); }' > css/site.css
+touch img/logo.png img/logo@2x.png 'search.php%3Fq=1.html'
+
+# Test: problems are reported with their page and line
+output=$("$HOPEN_BIN" check 2>&1)
+assert_exit_code 1 $? "hopen check exits with 1 when links are broken"
+assert_contains "$output" "/index.html:4  missing anchor  docs/page.html#nowhere" "Missing anchors are reported with page and line"
+assert_contains "$output" "/index.html:5  broken link  missing.html" "Broken links are reported"
+assert_contains "$output" "/index.html:6  outside root  ../outside.html" "References leaving the root are reported"
+assert_contains "$output" "/css/site.css:1  broken link  ../img/bg.png" "url() references in stylesheets are checked"
+assert_contains "$output" "img/Logo@2x.png" "srcset candidates are checked"
+assert_not_contains "$output" "commented-out.html" "Comments are skipped"
+assert_not_contains "$output" "in-script.html" "Scripts are skipped"
+assert_not_contains "$output" "search.php" "Query URLs resolve to the files mirroring tools saved"
+assert_not_contains "$output" "elsewhere.test" "External links aren't checked"
+
+# Test: the server's resolution options apply
+touch img/bg.png
+rm -f missing.html
+cat > index.html <<'HTML'
+<a href="docs/page.html#intro">Intro</a> <a href="https://www.example.com/docs/">Docs</a>
+<img src="img/Logo@2x.png">
+HTML
+"$HOPEN_BIN" check >/dev/null 2>&1
+assert_exit_code 1 $? "Case mismatches are broken without --ignore-case"
+output=$("$HOPEN_BIN" check --ignore-case --rewrite-origin https://www.example.com "$CHECK_DIR/site" 2>&1)
+assert_exit_code 0 $? "hopen check exits with 0 when all links work"
+assert_contains "$output" "No broken links" "hopen check --ignore-case accepts case fixups"
+output=$(cd /tmp && "$HOPEN_BIN" --json check --ignore-case "$CHECK_DIR/site" 2>/dev/null)
+assert_contains "$output" '"problems":[]' "hopen check --json reports the problems as JSON"
+
+cd /tmp
+rm -rf "$CHECK_DIR"
+
+echo ""
+
+# ============================================================================
+# Section 32: Sitemap
+# ============================================================================
+echo -e "${BOLD}--- Sitemap Tests (hopen sitemap, /__hopen/sitemap) ---${NC}"
+
+cleanup_servers
+
+SITEMAP_DIR=$(mktemp -d)
+mkdir -p "$SITEMAP_DIR/docs" "$SITEMAP_DIR/.git"
+cd "$SITEMAP_DIR"
+echo '<title>Home,  sweet &amp; home</title><a href="docs/">Docs</a> <a href="#top">Top</a> <a href="https://example.com/">Out</a> <img src="logo.png">' > index.html
+echo '<title>Guide</title><a href="../index.html">Home</a>' > docs/guide.html
+echo '<p>Docs</p>' > docs/index.html
+echo '<title>Secret</title>' > .git/secret.html
+
+# Test: sitemap.xml
+output=$("$HOPEN_BIN" sitemap 2>/dev/null)
+assert_contains "$output" '<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">' "hopen sitemap prints sitemap.xml"
+assert_contains "$output" "<loc>http://localhost:8000/docs/</loc>" "Index pages are listed as their directory"
+assert_contains "$output" "<lastmod>" "sitemap.xml has the last modification"
+assert_not_contains "$output" "secret" "Hidden files aren't listed"
+output=$("$HOPEN_BIN" sitemap --base-url https://www.example.com 2>/dev/null)
+assert_contains "$output" "<loc>https://www.example.com/docs/guide.html</loc>" "--base-url sets the origin of the URLs"
+
+# Test: CSV and JSON inventories
+output=$("$HOPEN_BIN" sitemap --format csv 2>/dev/null)
+assert_contains "$output" "url,title,size,last_modified,links" "The CSV inventory has a header"
+assert_contains "$output" '/,"Home, sweet & home",' "The CSV inventory has the titles, quoted when needed"
+assert_contains "$output" "/docs/guide.html,Guide," "Each page has a row"
+output=$("$HOPEN_BIN" --json sitemap 2>/dev/null | python3 -c 'import json, sys; page = json.load(sys.stdin)["pages"][0]; print(page["url"], page["links"])' 2>/dev/null)
+assert_equals "/ 2" "$output" "The JSON inventory counts the outbound links"
+"$HOPEN_BIN" sitemap -o "$SITEMAP_DIR/out.xml" -o "$SITEMAP_DIR/out.csv" >/dev/null 2>&1
+output=$(head -1 "$SITEMAP_DIR/out.csv" 2>/dev/null; head -1 "$SITEMAP_DIR/out.xml" 2>/dev/null)
+assert_contains "$output" "url,title" "-o writes each file in the format of its extension (CSV)"
+assert_contains "$output" "<?xml" "-o writes each file in the format of its extension (XML)"
+"$HOPEN_BIN" sitemap -o "$SITEMAP_DIR/out.txt" >/dev/null 2>&1
+assert_exit_code 1 $? "hopen sitemap refuses files of unknown format"
+
+# Test: the live sitemap
+"$HOPEN_BIN" --no-open >/dev/null 2>&1
+output=$(curl -s http://localhost:8000/__hopen/sitemap 2>/dev/null)
+assert_contains "$output" "3 pages" "/__hopen/sitemap lists the pages"
+output=$(curl -s "http://localhost:8000/__hopen/sitemap?format=xml" 2>/dev/null)
+assert_contains "$output" "<loc>http://localhost:8000/docs/guide.html</loc>" "/__hopen/sitemap?format=xml uses the request's host"
+echo '<title>New</title>' > new.html
+output=$(curl -s "http://localhost:8000/__hopen/sitemap?format=csv" 2>/dev/null)
+assert_contains "$output" "/new.html,New," "The live sitemap includes new pages"
+
+cleanup_servers
+cd /tmp
+rm -rf "$SITEMAP_DIR"
+
+echo ""
+
+# ============================================================================
+# Section 33: Site Search
+# ============================================================================
+echo -e "${BOLD}--- Search Tests (/__hopen/search) ---${NC}"
+
+cleanup_servers
+
+SEARCH_DIR=$(mktemp -d)
+mkdir -p "$SEARCH_DIR/docs" "$SEARCH_DIR/.git"
+cd "$SEARCH_DIR"
+echo '<title>Rust Guide</title><script>var hiddenword = 1;</script><h1>Welcome</h1><p>Rust is a <b>program</b>ming language. Caf&eacute; &amp; croissants.</p>' > index.html
+echo '<title>Other</title><p>Notes about rust and python.</p>' > docs/other.html
+echo '<title>Secret</title><p>rust python</p>' > .git/secret.html
+echo '<title>Edge</title><p>ẞ€foo İstanbul</p>' > edge.html
+echo '<title>Amps</title><p>Salt &amp; amplifiers &lt;3</p>' > amp.html
+
+"$HOPEN_BIN" --no-open >/dev/null 2>&1
+
+# Test: the JSON API
+output=$(curl -s "http://localhost:8000/__hopen/search?q=rust&format=json" 2>/dev/null | python3 -c 'import json, sys; r = json.load(sys.stdin); print(r["total"], " ".join(x["url"] for x in r["results"]))' 2>/dev/null)
+assert_equals "2 / /docs/other.html" "$output" "/__hopen/search finds the pages, title matches first"
+output=$(curl -s "http://localhost:8000/__hopen/search?q=Rust+PYTHON&format=json" 2>/dev/null)
+assert_contains "$output" '"total": 1' "Every word of the query must match"
+assert_contains "$output" '"snippet": "Notes about rust and python."' "Results come with a snippet"
+assert_not_contains "$output" "secret" "Hidden files aren't indexed"
+output=$(curl -s "http://localhost:8000/__hopen/search?q=program&format=json" 2>/dev/null)
+assert_contains "$output" "programming language" "Words are matched by prefix, across inline tags"
+output=$(curl -s "http://localhost:8000/__hopen/search?q=caf%C3%A9&format=json" 2>/dev/null)
+assert_contains "$output" '"total": 1' "Character references are decoded"
+output=$(curl -s "http://localhost:8000/__hopen/search?q=hiddenword&format=json" 2>/dev/null)
+assert_contains "$output" '"total": 0' "Scripts aren't indexed"
+
+# Test: the search page
+output=$(curl -s "http://localhost:8000/__hopen/search?q=python" 2>/dev/null)
+assert_contains "$output" '<input type="search" name="q" value="python"' "The search page has a search form"
+assert_contains "$output" "<mark>python</mark>" "Matches are highlighted in the snippets"
+output=$(curl -s "http://localhost:8000/__hopen/search?q=amp" 2>/dev/null)
+assert_contains "$output" "Salt &amp; <mark>amp</mark>lifiers &lt;3" "Snippets are escaped around the highlighted matches"
+assert_not_contains "$output" "&<mark>" "Character references aren't highlighted"
+
+# Test: letters whose lowercase has another length don't break snippets
+output=$(curl -s "http://localhost:8000/__hopen/search?q=foo&format=json" 2>/dev/null)
+assert_contains "$output" '"snippet": "ẞ€foo İstanbul"' "Snippets work around letters that change length when lowercased"
+output=$(curl -s "http://localhost:8000/__hopen/search?q=%C4%B0STANBUL" 2>/dev/null)
+assert_contains "$output" "<mark>İstanbul</mark>" "Matches are highlighted around letters that change length"
+
+# Test: the index follows changes, checking for them at most every 5 seconds
+echo '<title>Zebra</title><p>Crossing</p>' > new.html
+echo '<p>Rust, python and zebras</p>' > docs/other.html
+rm edge.html
+output=$(curl -s "http://localhost:8000/__hopen/search?q=zebra&format=json" 2>/dev/null)
+assert_contains "$output" '"total": 0' "Changes aren't looked for on every search"
+sleep 5
+output=$(curl -s "http://localhost:8000/__hopen/search?q=zebra&format=json" 2>/dev/null)
+assert_contains "$output" '"url": "/new.html"' "New pages are indexed"
+output=$(curl -s "http://localhost:8000/__hopen/search?q=zebras&format=json" 2>/dev/null)
+assert_contains "$output" '"total": 1' "Changed pages are reindexed"
+output=$(curl -s "http://localhost:8000/__hopen/search?q=foo&format=json" 2>/dev/null)
+assert_contains "$output" '"total": 0' "Deleted pages are dropped"
+
+cleanup_servers
+cd /tmp
+rm -rf "$SEARCH_DIR"
+
+echo ""
+
 # ============================================================================
 # Cleanup
 # ============================================================================